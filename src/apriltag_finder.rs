@@ -0,0 +1,119 @@
+use std::time::Duration;
+
+use apriltag::{Detector, DetectorBuilder, Family, Image};
+use clap::ValueEnum;
+use opencv::core::{Mat, MatTraitConst, MatTraitConstManual, Point2f, Rect, Vector};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aruco_finder::upscale, errors::Error, Aruco, ArucoFinder, ArucoFinderSetting, MarkerDetector,
+    Result,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "camelCase")]
+#[value(rename_all = "camelCase")]
+pub enum AprilTagFamily {
+    Tag16h5,
+    Tag25h9,
+    Tag36h11,
+    TagCircle21h7,
+    TagCircle49h12,
+    TagStandard41h12,
+    TagStandard52h13,
+}
+
+impl AprilTagFamily {
+    fn family(self) -> Family {
+        match self {
+            Self::Tag16h5 => Family::tag_16h5(),
+            Self::Tag25h9 => Family::tag_25h9(),
+            Self::Tag36h11 => Family::tag_36h11(),
+            Self::TagCircle21h7 => Family::tag_circle_21h7(),
+            Self::TagCircle49h12 => Family::tag_circle_49h12(),
+            Self::TagStandard41h12 => Family::tag_standard_41h12(),
+            Self::TagStandard52h13 => Family::tag_standard_52h13(),
+        }
+    }
+}
+
+/// AprilTag detection with the same pose estimation as `ArucoFinder`. The
+/// marker length, camera and `marker_ids` come from the finder setting, its
+/// dictionary is not used.
+pub struct AprilTagFinder {
+    detector: Detector,
+    pose: ArucoFinder,
+    // frame sized, reused as long as the frame size stays
+    image: Option<Image>,
+    // part of `image` written by the last search
+    window: Option<Rect>,
+}
+
+impl AprilTagFinder {
+    pub fn new(family: AprilTagFamily, setting: ArucoFinderSetting) -> Result<Self> {
+        let detector = DetectorBuilder::new()
+            .add_family_bits(family.family(), 1)
+            .build()
+            .map_err(|e| Error::Config(format!("apriltag detector: {e:?}")))?;
+        Ok(Self {
+            detector,
+            pose: ArucoFinder::new(setting),
+            image: None,
+            window: None,
+        })
+    }
+}
+
+impl MarkerDetector for AprilTagFinder {
+    fn find_markers_in(
+        &mut self,
+        gray: &Mat,
+        roi: Rect,
+        scale: u32,
+        time_stamp: Duration,
+        arucos: &mut Vec<Aruco>,
+    ) -> Result<()> {
+        let (cols, rows) = (gray.cols() as usize, gray.rows() as usize);
+        let image = match &mut self.image {
+            Some(image) if image.width() == cols && image.height() == rows => image,
+            image => {
+                self.window = None;
+                image.insert(
+                    Image::zeros_with_stride(cols, rows, cols)
+                        .map_err(|e| Error::Config(format!("apriltag image: {e:?}")))?,
+                )
+            }
+        };
+        // only the window holds pixels, the rest stays flat so nothing is
+        // found outside it
+        if let Some(last) = self.window.replace(roi) {
+            for y in last.y..last.y + last.height {
+                for x in last.x..last.x + last.width {
+                    image[(x as usize, y as usize)] = 0;
+                }
+            }
+        }
+        let data = gray.data_bytes()?;
+        let step = gray.step1_def()?;
+        for y in roi.y as usize..(roi.y + roi.height) as usize {
+            for x in roi.x as usize..(roi.x + roi.width) as usize {
+                image[(x, y)] = data[y * step + x];
+            }
+        }
+        let mut corners = Vector::<Vector<Point2f>>::new();
+        let mut ids = Vector::<i32>::new();
+        for detection in self.detector.detect(image) {
+            // apriltag starts bottom left and goes the other way round
+            let c = detection.corners();
+            corners.push(
+                [c[3], c[2], c[1], c[0]]
+                    .iter()
+                    .map(|p| Point2f::new(p[0] as f32, p[1] as f32))
+                    .collect(),
+            );
+            ids.push(detection.id() as i32);
+        }
+        let corners = upscale(corners, Point2f::default(), scale);
+        self.pose.estimate_poses(&corners, &ids, time_stamp, arucos)
+    }
+}
@@ -0,0 +1,235 @@
+use std::time::Duration;
+
+use opencv::{
+    aruco::{
+        estimate_pose_board_def, estimate_pose_charuco_board_def, interpolate_corners_charuco_def,
+        Board, CharucoBoard, Dictionary, GridBoard,
+    },
+    core::{Mat, Point2f, Point3f, Ptr, ToInputArray, Vector},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::Error, Result};
+
+/// One marker of a custom board, corners in meters in the board frame in
+/// the order OpenCV detects them (top left, clockwise).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BoardMarker {
+    pub id: i32,
+    pub corners: [[f32; 3]; 4],
+}
+
+/// Layout of several markers that move as one rigid body. Lengths are in
+/// meters and the markers come from the finder's dictionary.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BoardSetting {
+    /// `cols x rows` markers with ids counting up from `first_id`.
+    Grid {
+        cols: i32,
+        rows: i32,
+        marker_length: f32,
+        separation: f32,
+        #[serde(default)]
+        first_id: i32,
+    },
+    /// `cols x rows` chessboard squares with markers in the white ones.
+    Charuco {
+        cols: i32,
+        rows: i32,
+        square_length: f32,
+        marker_length: f32,
+    },
+    /// Any arrangement, e.g. the markers glued inside a finger.
+    Custom { markers: Vec<BoardMarker> },
+}
+
+impl BoardSetting {
+    /// Ids of every marker on the board.
+    pub fn marker_ids(&self) -> Vec<i32> {
+        match self {
+            Self::Grid {
+                cols,
+                rows,
+                first_id,
+                ..
+            } => (*first_id..first_id + cols * rows).collect(),
+            // markers sit on every other square
+            Self::Charuco { cols, rows, .. } => (0..cols * rows / 2).collect(),
+            Self::Custom { markers } => markers.iter().map(|m| m.id).collect(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let positive = |v: f32| v.is_finite() && v > 0.;
+        match self {
+            Self::Grid {
+                cols,
+                rows,
+                marker_length,
+                separation,
+                ..
+            } => {
+                if *cols < 1 || *rows < 1 {
+                    return Err(Error::Config(format!(
+                        "grid board needs at least one column and row, got {cols}x{rows}"
+                    )));
+                }
+                if !(positive(*marker_length) && positive(*separation)) {
+                    return Err(Error::Config(format!(
+                        "grid board lengths must be positive, got marker_length {marker_length} separation {separation}"
+                    )));
+                }
+            }
+            Self::Charuco {
+                cols,
+                rows,
+                square_length,
+                marker_length,
+            } => {
+                if *cols < 2 || *rows < 2 {
+                    return Err(Error::Config(format!(
+                        "charuco board needs at least 2x2 squares, got {cols}x{rows}"
+                    )));
+                }
+                if !(positive(*marker_length) && marker_length < square_length) {
+                    return Err(Error::Config(format!(
+                        "charuco marker_length must be positive and below square_length, got {marker_length} and {square_length}"
+                    )));
+                }
+            }
+            Self::Custom { markers } => {
+                if markers.is_empty() {
+                    return Err(Error::Config("custom board has no markers".into()));
+                }
+                if let Some(m) = markers
+                    .iter()
+                    .find(|m| m.corners.iter().flatten().any(|v| !v.is_finite()))
+                {
+                    return Err(Error::Config(format!(
+                        "corners of board marker {} are not finite",
+                        m.id
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn build(&self, dictionary: &Ptr<Dictionary>) -> Result<BoardModel> {
+        let model = match self {
+            Self::Grid {
+                cols,
+                rows,
+                marker_length,
+                separation,
+                first_id,
+            } => BoardModel::Markers(
+                GridBoard::create(
+                    *cols,
+                    *rows,
+                    *marker_length,
+                    *separation,
+                    dictionary,
+                    *first_id,
+                )?
+                .into(),
+            ),
+            Self::Charuco {
+                cols,
+                rows,
+                square_length,
+                marker_length,
+            } => BoardModel::Charuco(CharucoBoard::create(
+                *cols,
+                *rows,
+                *square_length,
+                *marker_length,
+                dictionary,
+            )?),
+            Self::Custom { markers } => {
+                let corners: Vector<Vector<Point3f>> = markers
+                    .iter()
+                    .map(|m| {
+                        m.corners
+                            .iter()
+                            .map(|c| Point3f::new(c[0], c[1], c[2]))
+                            .collect()
+                    })
+                    .collect();
+                let ids: Vector<i32> = markers.iter().map(|m| m.id).collect();
+                BoardModel::Markers(Board::create(&corners, dictionary, &ids)?)
+            }
+        };
+        Ok(model)
+    }
+}
+
+/// Pose of a whole board in the camera frame.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BoardPose {
+    pub trans: [f64; 3],
+    /// Roll, pitch and yaw of the board to camera rotation.
+    pub euler_angles: [f64; 3],
+    /// Ids of the detected board markers the pose was solved from.
+    pub markers: Vec<i32>,
+    pub time_stamp: Duration,
+}
+
+pub(crate) enum BoardModel {
+    Markers(Ptr<Board>),
+    Charuco(Ptr<CharucoBoard>),
+}
+
+impl BoardModel {
+    /// Rotation and translation vectors of the board, `None` when too few
+    /// of its markers were seen.
+    pub(crate) fn estimate(
+        &self,
+        img: &impl ToInputArray,
+        corners: &Vector<Vector<Point2f>>,
+        ids: &Vector<i32>,
+        camera_matrix: &Mat,
+        dist_coeffs: &Vector<f64>,
+    ) -> Result<Option<(Mat, Mat)>> {
+        let mut rvec = Mat::default();
+        let mut tvec = Mat::default();
+        let found = match self {
+            Self::Markers(board) => {
+                estimate_pose_board_def(
+                    corners,
+                    ids,
+                    board,
+                    camera_matrix,
+                    dist_coeffs,
+                    &mut rvec,
+                    &mut tvec,
+                )? > 0
+            }
+            Self::Charuco(board) => {
+                let mut charuco_corners = Vector::<Point2f>::new();
+                let mut charuco_ids = Vector::<i32>::new();
+                let count = interpolate_corners_charuco_def(
+                    corners,
+                    ids,
+                    img,
+                    board,
+                    &mut charuco_corners,
+                    &mut charuco_ids,
+                )?;
+                // the pose needs at least four corners
+                count >= 4
+                    && estimate_pose_charuco_board_def(
+                        &charuco_corners,
+                        &charuco_ids,
+                        board,
+                        camera_matrix,
+                        dist_coeffs,
+                        &mut rvec,
+                        &mut tvec,
+                    )?
+            }
+        };
+        Ok(found.then_some((rvec, tvec)))
+    }
+}
@@ -1,39 +1,104 @@
-use std::time::Duration;
+use std::{fs, path::Path, time::Duration};
 
-use crate::Result;
-use nalgebra::Rotation3;
+use crate::{
+    aruco_board::BoardModel, data_saver::FrameData, errors::Error, ArucoDictionary, BoardPose,
+    BoardSetting, CameraCalibration, DetectorSetting, Result,
+};
+use nalgebra::{Rotation3, UnitQuaternion};
 use opencv::{
     aruco::{
-        detect_markers, estimate_pose_single_markers_def, get_predefined_dictionary,
-        DetectorParameters, DetectorParametersTrait, Dictionary, PREDEFINED_DICTIONARY_NAME,
+        detect_markers, draw_detected_markers, estimate_pose_single_markers_def,
+        DetectorParameters, Dictionary,
+    },
+    calib3d::{draw_frame_axes_def, project_points_def, rodrigues_def},
+    core::{
+        no_array, Mat, MatTraitConstManual, Point2f, Point3f, Ptr, Rect, Scalar, ToInputArray,
+        Vec3d, Vector,
     },
-    calib3d::rodrigues_def,
-    core::{no_array, Mat, MatTraitConstManual, Point2f, Ptr, ToInputArray, Vec3d, Vector},
 };
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Aruco {
     pub id: i32,
     pub corners: [[f32; 2];4],
     pub trans: [f64; 3],
+    /// Roll, pitch and yaw of `rotation`.
     pub euler_angles: [f64; 3],
+    /// Marker to camera rotation, row-major.
+    pub rotation: [[f64; 3]; 3],
+    /// The same rotation as a unit quaternion `[x, y, z, w]`, free of the
+    /// singularities of `euler_angles`.
+    pub quaternion: [f64; 4],
     pub time_stamp: Duration,
+    pub quality: MarkerQuality,
+}
+
+impl FrameData for Aruco {
+    fn time_stamp(&self) -> Duration {
+        self.time_stamp
+    }
 }
 
+// errors and areas at which the score drops to one half
+const REPROJECTION_ERROR_HALF: f64 = 1.0;
+const AREA_HALF: f64 = 1000.0;
+
+/// How far a detection can be trusted.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct MarkerQuality {
+    /// RMS distance in pixels between the detected corners and the corners
+    /// of the estimated pose projected back into the image.
+    pub reprojection_error: f64,
+    /// Marker area in pixels at full resolution.
+    pub area: f64,
+    /// Angle in radians between the marker normal and the line of sight.
+    pub view_angle: f64,
+    /// 0 to 1, the product of one term per metric. Small, tilted markers
+    /// and poses that do not fit their corners score low.
+    pub score: f64,
+}
+
+impl MarkerQuality {
+    fn new(reprojection_error: f64, area: f64, view_angle: f64) -> Self {
+        let score = REPROJECTION_ERROR_HALF / (REPROJECTION_ERROR_HALF + reprojection_error)
+            * (area / (area + AREA_HALF))
+            * view_angle.cos().max(0.);
+        Self {
+            reprojection_error,
+            area,
+            view_angle,
+            score,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ArucoIntrinsic {
+    /// Side of the black square in meters.
     marker_length: f32,
-    dictionary: PREDEFINED_DICTIONARY_NAME,
+    #[serde(default)]
+    dictionary: ArucoDictionary,
 }
 
 impl ArucoIntrinsic {
-    pub fn new_with_marker_length(marker_length: f32) -> Self {
+    pub fn new(marker_length: f32, dictionary: ArucoDictionary) -> Self {
         Self {
             marker_length,
-            dictionary: PREDEFINED_DICTIONARY_NAME::DICT_4X4_50,
+            dictionary,
         }
     }
+
+    /// Markers from `DICT_4X4_50`.
+    pub fn new_with_marker_length(marker_length: f32) -> Self {
+        Self::new(marker_length, ArucoDictionary::default())
+    }
 }
 
+/// Pinhole camera parameters in pixels. Besides `fx`, `fy`, `cx`, `cy` the
+/// file may give the full 3x3 `camera_matrix` as OpenCV writes it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "IntrinsicRepr")]
 pub struct CameraIntrinsic {
     pub cx: f64,
     pub cy: f64,
@@ -41,7 +106,63 @@ pub struct CameraIntrinsic {
     pub fy: f64,
 }
 
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IntrinsicRepr {
+    Params { cx: f64, cy: f64, fx: f64, fy: f64 },
+    Matrix { camera_matrix: [[f64; 3]; 3] },
+}
+
+impl TryFrom<IntrinsicRepr> for CameraIntrinsic {
+    type Error = String;
+
+    fn try_from(value: IntrinsicRepr) -> std::result::Result<Self, Self::Error> {
+        match value {
+            IntrinsicRepr::Params { cx, cy, fx, fy } => Ok(Self { cx, cy, fx, fy }),
+            IntrinsicRepr::Matrix { camera_matrix: m } => {
+                if m[0][1] != 0. || m[1][0] != 0. || m[2] != [0., 0., 1.] {
+                    return Err(format!(
+                        "camera_matrix must look like [[fx, 0, cx], [0, fy, cy], [0, 0, 1]], got {m:?}"
+                    ));
+                }
+                Ok(Self {
+                    cx: m[0][2],
+                    cy: m[1][2],
+                    fx: m[0][0],
+                    fy: m[1][1],
+                })
+            }
+        }
+    }
+}
+
+impl CameraIntrinsic {
+    pub fn validate(&self) -> Result<()> {
+        let positive = |v: f64| v.is_finite() && v > 0.;
+        if !(positive(self.fx) && positive(self.fy)) {
+            return Err(Error::Config(format!(
+                "focal lengths must be positive, got fx {} fy {}",
+                self.fx, self.fy
+            )));
+        }
+        if !(self.cx.is_finite() && self.cx >= 0. && self.cy.is_finite() && self.cy >= 0.) {
+            return Err(Error::Config(format!(
+                "principal point must not be negative, got cx {} cy {}",
+                self.cx, self.cy
+            )));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn camera_matrix(&self) -> [[f64; 3]; 3] {
+        [[self.fx, 0., self.cx], [0., self.fy, self.cy], [0., 0., 1.]]
+    }
+}
+
 //  inline formula
+/// Stored in files as the plain list of 4, 5, 8 or 12 coefficients.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "Vec<f64>", into = "Vec<f64>")]
 pub enum CameraDistortion {
     // k1, k2, p1, p2
     Distortion4([f64; 4]),
@@ -57,7 +178,7 @@ pub enum CameraDistortion {
 }
 
 impl CameraDistortion {
-    fn as_slice(&self) -> &[f64] {
+    pub(crate) fn as_slice(&self) -> &[f64] {
         use CameraDistortion::*;
         match self {
             Distortion4(d) => d.as_slice(),
@@ -68,6 +189,31 @@ impl CameraDistortion {
     }
 }
 
+impl TryFrom<Vec<f64>> for CameraDistortion {
+    type Error = String;
+
+    fn try_from(value: Vec<f64>) -> std::result::Result<Self, Self::Error> {
+        if let Some(v) = value.iter().find(|v| !v.is_finite()) {
+            return Err(format!("distortion coefficient {v} is not finite"));
+        }
+        let n = value.len();
+        let wrong = |_| format!("need 4, 5, 8 or 12 distortion coefficients, got {n}");
+        Ok(match n {
+            4 => Self::Distortion4(value.try_into().map_err(wrong)?),
+            5 => Self::Distortion5(value.try_into().map_err(wrong)?),
+            8 => Self::Distortion8(value.try_into().map_err(wrong)?),
+            12 => Self::Distortion12(value.try_into().map_err(wrong)?),
+            _ => return Err(wrong(value)),
+        })
+    }
+}
+
+impl From<CameraDistortion> for Vec<f64> {
+    fn from(value: CameraDistortion) -> Self {
+        value.as_slice().to_vec()
+    }
+}
+
 impl CameraDistortion {
     pub fn from_4_params(k1: f64, k2: f64, p1: f64, p2: f64) -> Self {
         Self::Distortion4([k1, k2, p1, p2])
@@ -78,10 +224,125 @@ impl CameraDistortion {
     }
 }
 
+/// Everything `ArucoFinder` needs, loadable from TOML or JSON:
+///
+/// ```toml
+/// camera_distortion = [0.0097, -0.00745, 0.0, 0.0, 0.0]
+///
+/// [aruco_intrinsic]
+/// marker_length = 0.05
+/// dictionary = "DICT_4X4_50"
+///
+/// [detector]
+/// corner_refinement_method = "subpix"
+///
+/// [camera_intrinsic]
+/// fx = 971.2
+/// fy = 970.7
+/// cx = 655.4
+/// cy = 367.5
+///
+/// [board]
+/// type = "grid"
+/// cols = 4
+/// rows = 3
+/// marker_length = 0.04
+/// separation = 0.01
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ArucoFinderSetting {
     pub aruco_intrinsic: ArucoIntrinsic,
     pub camera_intrinsic: CameraIntrinsic,
     pub camera_distortion: CameraDistortion,
+    #[serde(default)]
+    pub detector: DetectorSetting,
+    /// Markers to solve as one rigid body with `ArucoFinder::find_board`.
+    #[serde(default)]
+    pub board: Option<BoardSetting>,
+    /// IDs of the markers that belong to this finger, most preferred first.
+    /// Other markers are ignored, an empty list accepts any.
+    #[serde(default)]
+    pub marker_ids: Vec<i32>,
+}
+
+impl ArucoFinderSetting {
+    /// Read a setting file, TOML when the extension says so, JSON otherwise.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let setting: Self = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&text)?,
+            _ => serde_json::from_str(&text)?,
+        };
+        setting.validate()?;
+        Ok(setting)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let text = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::to_string_pretty(self)?,
+            _ => serde_json::to_string_pretty(self)?,
+        };
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let marker_length = self.aruco_intrinsic.marker_length;
+        if !(marker_length.is_finite() && marker_length > 0.) {
+            return Err(Error::Config(format!(
+                "marker_length must be positive, got {marker_length}"
+            )));
+        }
+        self.camera_intrinsic.validate()?;
+        self.detector.validate()?;
+        match &self.board {
+            Some(board) => board.validate(),
+            None => Ok(()),
+        }
+    }
+
+    /// `validate` plus the checks that need the size of the frames the
+    /// setting is used with.
+    pub fn validate_for_image(&self, width: u32, height: u32) -> Result<()> {
+        self.validate()?;
+        let CameraIntrinsic { cx, cy, .. } = self.camera_intrinsic;
+        if cx >= width as f64 || cy >= height as f64 {
+            return Err(Error::Config(format!(
+                "principal point must lie in the {width}x{height} image, got cx {cx} cy {cy}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Position of `id` in `marker_ids`, `None` for a marker that does not
+    /// belong here.
+    pub fn marker_rank(&self, id: i32) -> Option<usize> {
+        if self.marker_ids.is_empty() {
+            return Some(0);
+        }
+        self.marker_ids.iter().position(|&m| m == id)
+    }
+
+    /// Camera parameters from a calibration file, rescaled to the resolution
+    /// frames are captured at.
+    pub fn from_calibration(
+        aruco_intrinsic: ArucoIntrinsic,
+        calibration: &CameraCalibration,
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        let calibration = calibration.scaled_to(width, height)?;
+        Ok(Self {
+            aruco_intrinsic,
+            camera_intrinsic: calibration.camera_intrinsic,
+            camera_distortion: calibration.camera_distortion,
+            detector: DetectorSetting::default(),
+            board: None,
+            marker_ids: vec![],
+        })
+    }
 }
 
 pub struct ArucoFinder {
@@ -90,64 +351,115 @@ pub struct ArucoFinder {
     setting: ArucoFinderSetting,
     camera_matrix: Mat,
     dist_coeffs: Vector<f64>,
+    // marker corners in the marker frame, in the order OpenCV detects them
+    marker_points: Vector<Point3f>,
+    board: Option<(BoardModel, Vec<i32>)>,
 }
 
 impl ArucoFinder {
     pub fn new(setting: ArucoFinderSetting) -> Self {
-        let dictionary = get_predefined_dictionary(setting.aruco_intrinsic.dictionary).unwrap();
-        let camera_matrix = Mat::from_slice_2d(&[
-            &[setting.camera_intrinsic.fx, 0., setting.camera_intrinsic.cx],
-            &[0., setting.camera_intrinsic.fy, setting.camera_intrinsic.cy],
-            &[0., 0., 0.],
-        ])
-        .unwrap();
+        let dictionary = setting.aruco_intrinsic.dictionary.build().unwrap();
+        let camera_matrix = Mat::from_slice_2d(&setting.camera_intrinsic.camera_matrix()).unwrap();
         let dist_coeffs = Vector::from_slice(setting.camera_distortion.as_slice());
-        let mut detector_paramter = Ptr::new(DetectorParameters::default().unwrap());
-        // detector_paramter.set_use_aruco3_detection(true);
-        detector_paramter.set_adaptive_thresh_win_size_min(100);
-        detector_paramter.set_adaptive_thresh_win_size_max(200);
-        detector_paramter.set_adaptive_thresh_win_size_step(50);
-        detector_paramter.set_min_marker_perimeter_rate(0.2);
-        // detector_paramter.adaptiveThreshWinSizeMin = 100;
-        // detector_paramter.adaptiveThreshWinSizeMax = 200;
-        // detector_paramter.adaptiveThreshWinSizeStep = 50;
-        // parameter.adaptiveThreshConstant = 7;
-        // parameter.minMarkerPerimeterRate = 0.1;
-        // parameter.maxMarkerPerimeterRate = 4;
-        // parameter.polygonalApproxAccuracyRate = 0.1;
-        // parameter.minCornerDistanceRate = 0.05;
-        // parameter.minDistanceToBorder = 3;
-        // parameter.minMarkerDistanceRate = 0.05;
-        // // parameter.cornerRefinementMethod = cv.CORNER_REFINE_NONE;
-        // // parameter.cornerRefinementWinSize = 5;
-        // // parameter.cornerRefinementMaxIterations = 30;
-        // // parameter.cornerRefinementMinAccuracy = 0.1;
-        // parameter.markerBorderBits = 1;
-        // parameter.perspectiveRemovePixelPerCell = 2;
-        // parameter.perspectiveRemoveIgnoredMarginPerCell = 0.13;
-        // parameter.maxErroneousBitsInBorderRate = 0.35;
-        // parameter.minOtsuStdDev = 5.0;
-        // parameter.errorCorrectionRate = 0.6;
+        let detector_paramter = setting.detector.build().unwrap();
+        let board = setting
+            .board
+            .as_ref()
+            .map(|b| (b.build(&dictionary).unwrap(), b.marker_ids()));
+        let half = setting.aruco_intrinsic.marker_length / 2.;
+        let marker_points = Vector::from_slice(&[
+            Point3f::new(-half, half, 0.),
+            Point3f::new(half, half, 0.),
+            Point3f::new(half, -half, 0.),
+            Point3f::new(-half, -half, 0.),
+        ]);
         Self {
             dictionary,
             detector_paramter,
             setting,
             camera_matrix,
             dist_coeffs,
+            marker_points,
+            board,
         }
     }
 
+    fn quality(
+        &self,
+        corners: &Vector<Point2f>,
+        rotation: &Rotation3<f64>,
+        rvec: &Vec3d,
+        tvec: &Vec3d,
+    ) -> Result<MarkerQuality> {
+        let mut projected = Vector::<Point2f>::new();
+        project_points_def(
+            &self.marker_points,
+            rvec,
+            tvec,
+            &self.camera_matrix,
+            &self.dist_coeffs,
+            &mut projected,
+        )?;
+        let squared: f64 = corners
+            .iter()
+            .zip(projected.iter())
+            .map(|(c, p)| ((c.x - p.x).powi(2) + (c.y - p.y).powi(2)) as f64)
+            .sum();
+        let reprojection_error = (squared / corners.len() as f64).sqrt();
+
+        // shoelace formula
+        let c = corners.as_slice();
+        let area = (0..c.len())
+            .map(|i| {
+                let (a, b) = (c[i], c[(i + 1) % c.len()]);
+                (a.x * b.y - b.x * a.y) as f64
+            })
+            .sum::<f64>()
+            .abs()
+            / 2.;
+
+        // marker z axis in camera coordinates
+        let normal = rotation.matrix().column(2).into_owned();
+        let sight = nalgebra::Vector3::new(tvec.0[0], tvec.0[1], tvec.0[2]);
+        let cos = normal.dot(&sight).abs() / sight.norm().max(f64::EPSILON);
+        let view_angle = cos.clamp(0., 1.).acos();
+
+        Ok(MarkerQuality::new(reprojection_error, area, view_angle))
+    }
+
+    pub fn setting(&self) -> &ArucoFinderSetting {
+        &self.setting
+    }
+
+    /// The detection `marker_ids` prefers most, if any is present.
+    pub fn select<'a>(&self, arucos: &'a [Aruco]) -> Option<&'a Aruco> {
+        arucos
+            .iter()
+            .filter_map(|a| Some((self.setting.marker_rank(a.id)?, a)))
+            .min_by_key(|(rank, _)| *rank)
+            .map(|(_, a)| a)
+    }
+
     pub fn find(
         &self,
         img: &impl ToInputArray,
         time_stamp: Duration,
         arucos: &mut Vec<Aruco>,
     ) -> Result<()> {
-        arucos.clear();
+        self.find_scaled(img, 1, time_stamp, arucos)
+    }
+
+    // markers in the image, corners mapped back to full resolution
+    fn detect(
+        &self,
+        img: &impl ToInputArray,
+        origin: Point2f,
+        scale: u32,
+        rejected: Option<&mut Vector<Vector<Point2f>>>,
+    ) -> Result<(Vector<Vector<Point2f>>, Vector<i32>)> {
         let mut corners = Vector::<Vector<Point2f>>::new();
         let mut ids = Vector::<i32>::new();
-        let mut rvecs = Vector::<Vec3d>::new();
-        let mut tvecs = Vector::<Vec3d>::new();
+        let mut candidates = Vector::<Vector<Point2f>>::new();
         // detect_markers_def(img, &self.dictionary, &mut corners, &mut ids)?;
         detect_markers(
             img,
@@ -155,26 +467,89 @@ impl ArucoFinder {
             &mut corners,
             &mut ids,
             &self.detector_paramter,
-            &mut no_array(),
+            &mut candidates,
         )?;
+        if let Some(rejected) = rejected {
+            *rejected = upscale(candidates, origin, scale);
+        }
+        Ok((upscale(corners, origin, scale), ids))
+    }
+
+    /// Like `find`, for an image shrunk by `scale`. Corners are mapped back
+    /// to full resolution before the pose is estimated, so the camera
+    /// intrinsics stay the same.
+    pub fn find_scaled(
+        &self,
+        img: &impl ToInputArray,
+        scale: u32,
+        time_stamp: Duration,
+        arucos: &mut Vec<Aruco>,
+    ) -> Result<()> {
+        self.find_with_rejected(img, scale, time_stamp, arucos, None)
+    }
+
+    /// Like `find_scaled`, also handing out the candidates that were
+    /// rejected as markers, for `annotate`.
+    pub fn find_with_rejected(
+        &self,
+        img: &impl ToInputArray,
+        scale: u32,
+        time_stamp: Duration,
+        arucos: &mut Vec<Aruco>,
+        rejected: Option<&mut Vector<Vector<Point2f>>>,
+    ) -> Result<()> {
+        let (corners, ids) = self.detect(img, Point2f::default(), scale, rejected)?;
+        self.estimate_poses(&corners, &ids, time_stamp, arucos)
+    }
+
+    /// Like `find_scaled`, searching only `roi` of the shrunk image.
+    pub fn find_in(
+        &self,
+        img: &Mat,
+        roi: Rect,
+        scale: u32,
+        time_stamp: Duration,
+        arucos: &mut Vec<Aruco>,
+    ) -> Result<()> {
+        let window = Mat::roi(img, roi)?;
+        let origin = Point2f::new(roi.x as f32, roi.y as f32);
+        let (corners, ids) = self.detect(&window, origin, scale, None)?;
+        self.estimate_poses(&corners, &ids, time_stamp, arucos)
+    }
+
+    /// Pose and quality of markers found by any detector, corners at full
+    /// resolution in the order OpenCV uses.
+    pub(crate) fn estimate_poses(
+        &self,
+        corners: &Vector<Vector<Point2f>>,
+        ids: &Vector<i32>,
+        time_stamp: Duration,
+        arucos: &mut Vec<Aruco>,
+    ) -> Result<()> {
+        arucos.clear();
         if corners.is_empty() {
             return Ok(());
         }
+        let mut rvecs = Vector::<Vec3d>::new();
+        let mut tvecs = Vector::<Vec3d>::new();
         estimate_pose_single_markers_def(
-            &corners,
+            corners,
             self.setting.aruco_intrinsic.marker_length,
             &self.camera_matrix,
             &self.dist_coeffs,
             &mut rvecs,
             &mut tvecs,
         )?;
-        for (index, (id, (rvec, tvec))) in ids.iter().zip(rvecs.iter().zip(tvecs.iter())).enumerate() {
-            let mut m = Mat::default();
-            rodrigues_def(&rvec, &mut m)?;
-            let m = nalgebra::Matrix3::from_iterator(m.iter::<f64>()?.map(|(_, v)| v));
-            let (r, p, y) = Rotation3::from_matrix(&m).euler_angles();
-            let c = corners.get(index).unwrap();
-            let c = c.as_slice();
+        for (index, (id, (rvec, tvec))) in
+            ids.iter().zip(rvecs.iter().zip(tvecs.iter())).enumerate()
+        {
+            if self.setting.marker_rank(id).is_none() {
+                continue;
+            }
+            let rotation = rotation(&rvec)?;
+            let detected = corners.get(index).unwrap();
+            let quality = self.quality(&detected, &rotation, &rvec, &tvec)?;
+            let c = detected.as_slice();
             arucos.push(Aruco {
                 id,
                 corners: [
@@ -185,9 +560,132 @@ impl ArucoFinder {
                 ],
                 time_stamp,
                 trans: [tvec.0[0], tvec.0[1], tvec.0[2]],
-                euler_angles: [r, p, y],
+                euler_angles: euler_angles(&rotation),
+                rotation: rotation_rows(&rotation),
+                quaternion: quaternion(&rotation),
+                quality,
             });
         }
         Ok(())
     }
+
+    /// Pose of the board from the setting as one rigid body, `None` when no
+    /// board is configured or too few of its markers are visible.
+    pub fn find_board(
+        &self,
+        img: &impl ToInputArray,
+        time_stamp: Duration,
+    ) -> Result<Option<BoardPose>> {
+        let Some((board, board_ids)) = &self.board else {
+            return Ok(None);
+        };
+        let (corners, ids) = self.detect(img, Point2f::default(), 1, None)?;
+        let markers: Vec<i32> = ids.iter().filter(|id| board_ids.contains(id)).collect();
+        if markers.is_empty() {
+            return Ok(None);
+        }
+        let Some((rvec, tvec)) =
+            board.estimate(img, &corners, &ids, &self.camera_matrix, &self.dist_coeffs)?
+        else {
+            return Ok(None);
+        };
+        let rotation = rotation(&rvec)?;
+        let t = tvec.data_typed::<f64>()?;
+        Ok(Some(BoardPose {
+            trans: [t[0], t[1], t[2]],
+            euler_angles: euler_angles(&rotation),
+            markers,
+            time_stamp,
+        }))
+    }
+
+    /// Draw rejected candidates in red, markers with their IDs in green and
+    /// the pose axes of each marker onto a full resolution BGR image.
+    pub fn annotate(
+        &self,
+        canvas: &mut Mat,
+        arucos: &[Aruco],
+        rejected: &Vector<Vector<Point2f>>,
+    ) -> Result<()> {
+        if !rejected.is_empty() {
+            draw_detected_markers(canvas, rejected, &no_array(), Scalar::new(0., 0., 255., 0.))?;
+        }
+        if arucos.is_empty() {
+            return Ok(());
+        }
+        let corners: Vector<Vector<Point2f>> = arucos
+            .iter()
+            .map(|a| a.corners.iter().map(|c| Point2f::new(c[0], c[1])).collect())
+            .collect();
+        let ids: Vector<i32> = arucos.iter().map(|a| a.id).collect();
+        draw_detected_markers(canvas, &corners, &ids, Scalar::new(0., 255., 0., 0.))?;
+        for aruco in arucos {
+            let rotation = Mat::from_slice_2d(&aruco.rotation)?;
+            let mut rvec = Mat::default();
+            rodrigues_def(&rotation, &mut rvec)?;
+            let tvec = Vec3d::from_array(aruco.trans);
+            draw_frame_axes_def(
+                canvas,
+                &self.camera_matrix,
+                &self.dist_coeffs,
+                &rvec,
+                &tvec,
+                self.setting.aruco_intrinsic.marker_length / 2.,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// corners found at `origin` of an image shrunk by `scale` to full resolution
+pub(crate) fn upscale(
+    corners: Vector<Vector<Point2f>>,
+    origin: Point2f,
+    scale: u32,
+) -> Vector<Vector<Point2f>> {
+    if scale <= 1 && origin == Point2f::default() {
+        return corners;
+    }
+    let scale = scale.max(1) as f32;
+    // pixel centres, not corners, line up between the two images
+    let offset = (scale - 1.) / 2.;
+    corners
+        .iter()
+        .map(|c| {
+            c.iter()
+                .map(|p| {
+                    Point2f::new(
+                        (p.x + origin.x) * scale + offset,
+                        (p.y + origin.y) * scale + offset,
+                    )
+                })
+                .collect::<Vector<Point2f>>()
+        })
+        .collect()
+}
+
+// marker to camera rotation of a rotation vector
+fn rotation(rvec: &impl ToInputArray) -> Result<Rotation3<f64>> {
+    let mut m = Mat::default();
+    rodrigues_def(rvec, &mut m)?;
+    // OpenCV hands the matrix out row by row
+    let rows: Vec<f64> = m.iter::<f64>()?.map(|(_, v)| v).collect();
+    let m = nalgebra::Matrix3::from_row_slice(&rows);
+    Ok(Rotation3::from_matrix_unchecked(m))
+}
+
+// roll, pitch, yaw
+pub(crate) fn euler_angles(rotation: &Rotation3<f64>) -> [f64; 3] {
+    let (r, p, y) = rotation.euler_angles();
+    [r, p, y]
+}
+
+pub(crate) fn rotation_rows(rotation: &Rotation3<f64>) -> [[f64; 3]; 3] {
+    let m = rotation.matrix();
+    [0, 1, 2].map(|i| [m[(i, 0)], m[(i, 1)], m[(i, 2)]])
+}
+
+pub(crate) fn quaternion(rotation: &Rotation3<f64>) -> [f64; 4] {
+    let q = UnitQuaternion::from_rotation_matrix(rotation);
+    [q.i, q.j, q.k, q.w]
 }
@@ -0,0 +1,289 @@
+use std::str::FromStr;
+
+use opencv::{
+    aruco::{
+        custom_dictionary, get_predefined_dictionary, CornerRefineMethod, DetectorParameters,
+        DetectorParametersTrait, Dictionary, PREDEFINED_DICTIONARY_NAME,
+    },
+    core::{vconcat, Mat, MatTraitConst, Ptr, Vector},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::Error, Result};
+
+/// Marker dictionary, one of OpenCV's predefined ones by name
+/// (`"DICT_5X5_100"`) or a custom one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ArucoDictionary {
+    Predefined(#[serde(with = "dictionary_name")] PREDEFINED_DICTIONARY_NAME),
+    /// `markers` random markers of `marker_size x marker_size` bits, the
+    /// same seed always gives the same dictionary.
+    Generated {
+        markers: i32,
+        marker_size: i32,
+        #[serde(default)]
+        seed: i32,
+    },
+    /// Markers given bit by bit, each a row-major list of
+    /// `marker_size * marker_size` zeros and ones.
+    Bits {
+        marker_size: i32,
+        markers: Vec<Vec<u8>>,
+        #[serde(default)]
+        max_correction_bits: i32,
+    },
+}
+
+impl Default for ArucoDictionary {
+    fn default() -> Self {
+        Self::Predefined(PREDEFINED_DICTIONARY_NAME::DICT_4X4_50)
+    }
+}
+
+impl FromStr for ArucoDictionary {
+    type Err = Error;
+
+    /// A predefined dictionary by name, e.g. `DICT_6X6_250`.
+    fn from_str(s: &str) -> Result<Self> {
+        dictionary_name::parse(s)
+            .map(Self::Predefined)
+            .ok_or_else(|| Error::Config(format!("unknown aruco dictionary {s}")))
+    }
+}
+
+impl ArucoDictionary {
+    pub fn build(&self) -> Result<Ptr<Dictionary>> {
+        match self {
+            Self::Predefined(name) => Ok(get_predefined_dictionary(*name)?),
+            Self::Generated {
+                markers,
+                marker_size,
+                seed,
+            } => Ok(custom_dictionary(*markers, *marker_size, *seed)?),
+            Self::Bits {
+                marker_size,
+                markers,
+                max_correction_bits,
+            } => {
+                let bits_len = (marker_size * marker_size) as usize;
+                let mut byte_lists = Vector::<Mat>::new();
+                for (i, marker) in markers.iter().enumerate() {
+                    if marker.len() != bits_len {
+                        return Err(Error::Config(format!(
+                            "marker {i} has {} bits, expected {bits_len}",
+                            marker.len()
+                        )));
+                    }
+                    let bits = Mat::from_slice(marker)?
+                        .reshape(1, *marker_size)?
+                        .try_clone()?;
+                    byte_lists.push(Dictionary::get_byte_list_from_bits(&bits)?);
+                }
+                let mut bytes = Mat::default();
+                vconcat(&byte_lists, &mut bytes)?;
+                Ok(Ptr::new(Dictionary::new(
+                    &bytes,
+                    *marker_size,
+                    *max_correction_bits,
+                )?))
+            }
+        }
+    }
+}
+
+// OpenCV's predefined dictionaries by their C++ names, e.g. "DICT_4X4_50"
+mod dictionary_name {
+    use opencv::aruco::PREDEFINED_DICTIONARY_NAME::{self, *};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    const NAMES: [(PREDEFINED_DICTIONARY_NAME, &str); 21] = [
+        (DICT_4X4_50, "DICT_4X4_50"),
+        (DICT_4X4_100, "DICT_4X4_100"),
+        (DICT_4X4_250, "DICT_4X4_250"),
+        (DICT_4X4_1000, "DICT_4X4_1000"),
+        (DICT_5X5_50, "DICT_5X5_50"),
+        (DICT_5X5_100, "DICT_5X5_100"),
+        (DICT_5X5_250, "DICT_5X5_250"),
+        (DICT_5X5_1000, "DICT_5X5_1000"),
+        (DICT_6X6_50, "DICT_6X6_50"),
+        (DICT_6X6_100, "DICT_6X6_100"),
+        (DICT_6X6_250, "DICT_6X6_250"),
+        (DICT_6X6_1000, "DICT_6X6_1000"),
+        (DICT_7X7_50, "DICT_7X7_50"),
+        (DICT_7X7_100, "DICT_7X7_100"),
+        (DICT_7X7_250, "DICT_7X7_250"),
+        (DICT_7X7_1000, "DICT_7X7_1000"),
+        (DICT_ARUCO_ORIGINAL, "DICT_ARUCO_ORIGINAL"),
+        (DICT_APRILTAG_16h5, "DICT_APRILTAG_16h5"),
+        (DICT_APRILTAG_25h9, "DICT_APRILTAG_25h9"),
+        (DICT_APRILTAG_36h10, "DICT_APRILTAG_36h10"),
+        (DICT_APRILTAG_36h11, "DICT_APRILTAG_36h11"),
+    ];
+
+    pub fn parse(name: &str) -> Option<PREDEFINED_DICTIONARY_NAME> {
+        NAMES
+            .iter()
+            .find(|(_, n)| n.eq_ignore_ascii_case(name))
+            .map(|(d, _)| *d)
+    }
+
+    pub fn serialize<S: Serializer>(
+        dictionary: &PREDEFINED_DICTIONARY_NAME,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let (_, name) = NAMES.iter().find(|(d, _)| d == dictionary).unwrap();
+        serializer.serialize_str(name)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<PREDEFINED_DICTIONARY_NAME, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        parse(&name).ok_or_else(|| D::Error::custom(format!("unknown aruco dictionary {name}")))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CornerRefinement {
+    #[default]
+    None,
+    Subpix,
+    Contour,
+    Apriltag,
+}
+
+impl From<CornerRefinement> for CornerRefineMethod {
+    fn from(value: CornerRefinement) -> Self {
+        match value {
+            CornerRefinement::None => CornerRefineMethod::CORNER_REFINE_NONE,
+            CornerRefinement::Subpix => CornerRefineMethod::CORNER_REFINE_SUBPIX,
+            CornerRefinement::Contour => CornerRefineMethod::CORNER_REFINE_CONTOUR,
+            CornerRefinement::Apriltag => CornerRefineMethod::CORNER_REFINE_APRILTAG,
+        }
+    }
+}
+
+/// OpenCV `DetectorParameters`, field for field. Fields left out of a file
+/// keep the defaults, which are OpenCV's except for the adaptive threshold
+/// windows and minimum perimeter tuned for the finger camera.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DetectorSetting {
+    pub adaptive_thresh_win_size_min: i32,
+    pub adaptive_thresh_win_size_max: i32,
+    pub adaptive_thresh_win_size_step: i32,
+    pub adaptive_thresh_constant: f64,
+    pub min_marker_perimeter_rate: f64,
+    pub max_marker_perimeter_rate: f64,
+    pub polygonal_approx_accuracy_rate: f64,
+    pub min_corner_distance_rate: f64,
+    pub min_distance_to_border: i32,
+    pub min_marker_distance_rate: f64,
+    pub corner_refinement_method: CornerRefinement,
+    pub corner_refinement_win_size: i32,
+    pub corner_refinement_max_iterations: i32,
+    pub corner_refinement_min_accuracy: f64,
+    pub marker_border_bits: i32,
+    pub perspective_remove_pixel_per_cell: i32,
+    pub perspective_remove_ignored_margin_per_cell: f64,
+    pub max_erroneous_bits_in_border_rate: f64,
+    pub min_otsu_std_dev: f64,
+    pub error_correction_rate: f64,
+    pub detect_inverted_marker: bool,
+    pub use_aruco3_detection: bool,
+    pub min_side_length_canonical_img: i32,
+    pub min_marker_length_ratio_original_img: f32,
+}
+
+impl Default for DetectorSetting {
+    fn default() -> Self {
+        Self {
+            adaptive_thresh_win_size_min: 100,
+            adaptive_thresh_win_size_max: 200,
+            adaptive_thresh_win_size_step: 50,
+            adaptive_thresh_constant: 7.,
+            min_marker_perimeter_rate: 0.2,
+            max_marker_perimeter_rate: 4.,
+            polygonal_approx_accuracy_rate: 0.03,
+            min_corner_distance_rate: 0.05,
+            min_distance_to_border: 3,
+            min_marker_distance_rate: 0.05,
+            corner_refinement_method: CornerRefinement::None,
+            corner_refinement_win_size: 5,
+            corner_refinement_max_iterations: 30,
+            corner_refinement_min_accuracy: 0.1,
+            marker_border_bits: 1,
+            perspective_remove_pixel_per_cell: 4,
+            perspective_remove_ignored_margin_per_cell: 0.13,
+            max_erroneous_bits_in_border_rate: 0.35,
+            min_otsu_std_dev: 5.,
+            error_correction_rate: 0.6,
+            detect_inverted_marker: false,
+            use_aruco3_detection: false,
+            min_side_length_canonical_img: 32,
+            min_marker_length_ratio_original_img: 0.,
+        }
+    }
+}
+
+impl DetectorSetting {
+    pub fn validate(&self) -> Result<()> {
+        let wins = (
+            self.adaptive_thresh_win_size_min,
+            self.adaptive_thresh_win_size_max,
+            self.adaptive_thresh_win_size_step,
+        );
+        if wins.0 < 3 || wins.1 < wins.0 || wins.2 <= 0 {
+            return Err(Error::Config(format!(
+                "adaptive threshold windows need 3 <= min <= max and step > 0, got {wins:?}"
+            )));
+        }
+        if self.min_marker_perimeter_rate > self.max_marker_perimeter_rate {
+            return Err(Error::Config(format!(
+                "min_marker_perimeter_rate {} is above max_marker_perimeter_rate {}",
+                self.min_marker_perimeter_rate, self.max_marker_perimeter_rate
+            )));
+        }
+        if self.marker_border_bits < 1 {
+            return Err(Error::Config(
+                "marker_border_bits must be at least 1".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn build(&self) -> Result<Ptr<DetectorParameters>> {
+        let mut p = DetectorParameters::default()?;
+        p.set_adaptive_thresh_win_size_min(self.adaptive_thresh_win_size_min);
+        p.set_adaptive_thresh_win_size_max(self.adaptive_thresh_win_size_max);
+        p.set_adaptive_thresh_win_size_step(self.adaptive_thresh_win_size_step);
+        p.set_adaptive_thresh_constant(self.adaptive_thresh_constant);
+        p.set_min_marker_perimeter_rate(self.min_marker_perimeter_rate);
+        p.set_max_marker_perimeter_rate(self.max_marker_perimeter_rate);
+        p.set_polygonal_approx_accuracy_rate(self.polygonal_approx_accuracy_rate);
+        p.set_min_corner_distance_rate(self.min_corner_distance_rate);
+        p.set_min_distance_to_border(self.min_distance_to_border);
+        p.set_min_marker_distance_rate(self.min_marker_distance_rate);
+        p.set_corner_refinement_method(
+            CornerRefineMethod::from(self.corner_refinement_method) as i32
+        );
+        p.set_corner_refinement_win_size(self.corner_refinement_win_size);
+        p.set_corner_refinement_max_iterations(self.corner_refinement_max_iterations);
+        p.set_corner_refinement_min_accuracy(self.corner_refinement_min_accuracy);
+        p.set_marker_border_bits(self.marker_border_bits);
+        p.set_perspective_remove_pixel_per_cell(self.perspective_remove_pixel_per_cell);
+        p.set_perspective_remove_ignored_margin_per_cell(
+            self.perspective_remove_ignored_margin_per_cell,
+        );
+        p.set_max_erroneous_bits_in_border_rate(self.max_erroneous_bits_in_border_rate);
+        p.set_min_otsu_std_dev(self.min_otsu_std_dev);
+        p.set_error_correction_rate(self.error_correction_rate);
+        p.set_detect_inverted_marker(self.detect_inverted_marker);
+        p.set_use_aruco3_detection(self.use_aruco3_detection);
+        p.set_min_side_length_canonical_img(self.min_side_length_canonical_img);
+        p.set_min_marker_length_ratio_original_img(self.min_marker_length_ratio_original_img);
+        Ok(Ptr::new(p))
+    }
+}
@@ -0,0 +1,255 @@
+use std::{fs, path::Path};
+
+use opencv::{
+    aruco::{
+        calibrate_camera_charuco_def, detect_markers_def, interpolate_corners_charuco_def,
+        CharucoBoard, Dictionary,
+    },
+    calib3d::{calibrate_camera_def, find_chessboard_corners_def},
+    core::{
+        Mat, MatTraitConst, MatTraitConstManual, Point2f, Point3f, Ptr, Size, TermCriteria,
+        TermCriteria_Type, Vector,
+    },
+    imgproc::corner_sub_pix,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::Error, ArucoDictionary, CameraDistortion, CameraIntrinsic, Result};
+
+/// Target held in front of the camera while calibrating.
+#[derive(Debug, Clone)]
+pub enum CalibrationPattern {
+    /// `cols x rows` inner corners, `square` in meters.
+    Chessboard { cols: u32, rows: u32, square: f32 },
+    /// `cols x rows` squares with ArUco markers in the white ones.
+    Charuco {
+        cols: u32,
+        rows: u32,
+        square: f32,
+        marker: f32,
+        dictionary: ArucoDictionary,
+    },
+}
+
+/// Result of a calibration, stored as JSON next to the camera it belongs to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraCalibration {
+    /// Resolution the views were captured at.
+    pub width: u32,
+    pub height: u32,
+    pub camera_intrinsic: CameraIntrinsic,
+    pub camera_distortion: CameraDistortion,
+    /// RMS reprojection error in pixels.
+    pub reprojection_error: f64,
+}
+
+impl CameraCalibration {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// The same calibration for a camera mode with another resolution but
+    /// the same field of view. Distortion does not depend on resolution.
+    ///
+    /// Modes with another aspect ratio usually crop the sensor, which
+    /// moves the principal point in a way a scale cannot describe, so they
+    /// are refused.
+    pub fn scaled_to(&self, width: u32, height: u32) -> Result<Self> {
+        if width as u64 * self.height as u64 != height as u64 * self.width as u64 {
+            return Err(Error::Config(format!(
+                "calibration for {}x{} does not scale to {width}x{height}, the aspect ratio differs",
+                self.width, self.height
+            )));
+        }
+        let sx = width as f64 / self.width as f64;
+        let sy = height as f64 / self.height as f64;
+        let i = &self.camera_intrinsic;
+        Ok(Self {
+            width,
+            height,
+            camera_intrinsic: CameraIntrinsic {
+                cx: i.cx * sx,
+                cy: i.cy * sy,
+                fx: i.fx * sx,
+                fy: i.fy * sy,
+            },
+            camera_distortion: self.camera_distortion.clone(),
+            reprojection_error: self.reprojection_error,
+        })
+    }
+}
+
+enum Board {
+    Chessboard {
+        pattern_size: Size,
+        corners: Vector<Point3f>,
+    },
+    Charuco {
+        board: Ptr<CharucoBoard>,
+        dictionary: Ptr<Dictionary>,
+    },
+}
+
+/// Collects views of a calibration pattern and runs the OpenCV calibration.
+pub struct Calibrator {
+    board: Board,
+    image_size: Option<Size>,
+    object_points: Vector<Vector<Point3f>>,
+    image_points: Vector<Vector<Point2f>>,
+    charuco_ids: Vector<Vector<i32>>,
+}
+
+impl Calibrator {
+    pub fn new(pattern: CalibrationPattern) -> Result<Self> {
+        let board = match pattern {
+            CalibrationPattern::Chessboard { cols, rows, square } => {
+                let corners = (0..rows)
+                    .flat_map(|r| {
+                        (0..cols)
+                            .map(move |c| Point3f::new(c as f32 * square, r as f32 * square, 0.))
+                    })
+                    .collect();
+                Board::Chessboard {
+                    pattern_size: Size::new(cols as i32, rows as i32),
+                    corners,
+                }
+            }
+            CalibrationPattern::Charuco {
+                cols,
+                rows,
+                square,
+                marker,
+                dictionary,
+            } => {
+                let dictionary = dictionary.build()?;
+                let board =
+                    CharucoBoard::create(cols as i32, rows as i32, square, marker, &dictionary)?;
+                Board::Charuco { board, dictionary }
+            }
+        };
+        Ok(Self {
+            board,
+            image_size: None,
+            object_points: Vector::new(),
+            image_points: Vector::new(),
+            charuco_ids: Vector::new(),
+        })
+    }
+
+    /// Number of views collected so far.
+    pub fn views(&self) -> usize {
+        self.image_points.len()
+    }
+
+    /// Look for the pattern in a gray image and keep the view when found.
+    pub fn add_view(&mut self, gray: &Mat) -> Result<bool> {
+        let size = gray.size()?;
+        if self.image_size.is_some_and(|s| s != size) {
+            return Err(Error::Config(format!(
+                "view is {}x{}, earlier views were {:?}",
+                size.width, size.height, self.image_size
+            )));
+        }
+        match &self.board {
+            Board::Chessboard {
+                pattern_size,
+                corners,
+            } => {
+                let mut image_corners = Vector::<Point2f>::new();
+                if !find_chessboard_corners_def(gray, *pattern_size, &mut image_corners)? {
+                    return Ok(false);
+                }
+                let criteria = TermCriteria::new(
+                    TermCriteria_Type::COUNT as i32 + TermCriteria_Type::EPS as i32,
+                    30,
+                    0.001,
+                )?;
+                corner_sub_pix(
+                    gray,
+                    &mut image_corners,
+                    Size::new(11, 11),
+                    Size::new(-1, -1),
+                    criteria,
+                )?;
+                self.object_points.push(corners.clone());
+                self.image_points.push(image_corners);
+            }
+            Board::Charuco { board, dictionary } => {
+                let mut marker_corners = Vector::<Vector<Point2f>>::new();
+                let mut marker_ids = Vector::<i32>::new();
+                detect_markers_def(gray, dictionary, &mut marker_corners, &mut marker_ids)?;
+                if marker_ids.is_empty() {
+                    return Ok(false);
+                }
+                let mut charuco_corners = Vector::<Point2f>::new();
+                let mut charuco_ids = Vector::<i32>::new();
+                let count = interpolate_corners_charuco_def(
+                    &marker_corners,
+                    &marker_ids,
+                    gray,
+                    board,
+                    &mut charuco_corners,
+                    &mut charuco_ids,
+                )?;
+                // fewer corners than this do not constrain the distortion
+                if count < 6 {
+                    return Ok(false);
+                }
+                self.image_points.push(charuco_corners);
+                self.charuco_ids.push(charuco_ids);
+            }
+        }
+        self.image_size = Some(size);
+        Ok(true)
+    }
+
+    /// Run the calibration on every view collected so far.
+    pub fn calibrate(&self) -> Result<CameraCalibration> {
+        let Some(image_size) = self.image_size else {
+            return Err(Error::Config("no calibration views".into()));
+        };
+        let mut camera_matrix = Mat::default();
+        let mut dist_coeffs = Mat::default();
+        let reprojection_error = match &self.board {
+            Board::Chessboard { .. } => calibrate_camera_def(
+                &self.object_points,
+                &self.image_points,
+                image_size,
+                &mut camera_matrix,
+                &mut dist_coeffs,
+                &mut Vector::<Mat>::new(),
+                &mut Vector::<Mat>::new(),
+            )?,
+            Board::Charuco { board, .. } => calibrate_camera_charuco_def(
+                &self.image_points,
+                &self.charuco_ids,
+                board,
+                image_size,
+                &mut camera_matrix,
+                &mut dist_coeffs,
+            )?,
+        };
+
+        let m = |r, c| camera_matrix.at_2d::<f64>(r, c).copied();
+        let coeffs = dist_coeffs.data_typed::<f64>()?;
+        let d = |i: usize| coeffs.get(i).copied().unwrap_or(0.);
+        Ok(CameraCalibration {
+            width: image_size.width as u32,
+            height: image_size.height as u32,
+            camera_intrinsic: CameraIntrinsic {
+                cx: m(0, 2)?,
+                cy: m(1, 2)?,
+                fx: m(0, 0)?,
+                fy: m(1, 1)?,
+            },
+            camera_distortion: CameraDistortion::from_5_params(d(0), d(1), d(2), d(3), d(4)),
+            reprojection_error,
+        })
+    }
+}
@@ -0,0 +1,150 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+use v4l::control::{self, Value};
+
+use crate::{errors::Error, Result};
+
+/// The V4L2 user and camera class controls we care about.
+///
+/// Variants are ordered so that the auto toggles come before the manual
+/// value they gate, drivers reject e.g. an exposure time while auto exposure
+/// is still on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CameraControl {
+    Brightness,
+    Contrast,
+    Saturation,
+    Sharpness,
+    BacklightCompensation,
+    PowerLineFrequency,
+    Gain,
+    // menu: 0 auto, 1 manual, 2 shutter priority, 3 aperture priority
+    AutoExposure,
+    // in 100 µs units
+    ExposureAbsolute,
+    ExposureAutoPriority,
+    AutoWhiteBalance,
+    // kelvin
+    WhiteBalanceTemperature,
+    AutoFocus,
+    FocusAbsolute,
+}
+
+impl CameraControl {
+    pub fn id(&self) -> u32 {
+        use CameraControl::*;
+        match self {
+            Brightness => 0x0098_0900,
+            Contrast => 0x0098_0901,
+            Saturation => 0x0098_0902,
+            AutoWhiteBalance => 0x0098_090c,
+            Gain => 0x0098_0913,
+            PowerLineFrequency => 0x0098_0918,
+            WhiteBalanceTemperature => 0x0098_091a,
+            Sharpness => 0x0098_091b,
+            BacklightCompensation => 0x0098_091c,
+            AutoExposure => 0x009a_0901,
+            ExposureAbsolute => 0x009a_0902,
+            ExposureAutoPriority => 0x009a_0903,
+            FocusAbsolute => 0x009a_090a,
+            AutoFocus => 0x009a_090c,
+        }
+    }
+
+    pub fn from_id(id: u32) -> Option<Self> {
+        use CameraControl::*;
+        [
+            Brightness,
+            Contrast,
+            Saturation,
+            Sharpness,
+            BacklightCompensation,
+            PowerLineFrequency,
+            Gain,
+            AutoExposure,
+            ExposureAbsolute,
+            ExposureAutoPriority,
+            AutoWhiteBalance,
+            WhiteBalanceTemperature,
+            AutoFocus,
+            FocusAbsolute,
+        ]
+        .into_iter()
+        .find(|c| c.id() == id)
+    }
+}
+
+pub(crate) const EXPOSURE_MANUAL: i64 = 1;
+pub(crate) const EXPOSURE_APERTURE_PRIORITY: i64 = 3;
+
+/// One control as reported by the device.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControlInfo {
+    pub id: u32,
+    pub control: Option<CameraControl>,
+    pub name: String,
+    pub minimum: i64,
+    pub maximum: i64,
+    pub step: u64,
+    pub default: i64,
+    // None for write only controls or types we do not read
+    pub value: Option<i64>,
+}
+
+impl ControlInfo {
+    pub(crate) fn new(desc: &control::Description, value: Option<i64>) -> Self {
+        Self {
+            id: desc.id,
+            control: CameraControl::from_id(desc.id),
+            name: desc.name.clone(),
+            minimum: desc.minimum,
+            maximum: desc.maximum,
+            step: desc.step,
+            default: desc.default,
+            value,
+        }
+    }
+}
+
+pub(crate) fn value_as_i64(value: &Value) -> Option<i64> {
+    match value {
+        Value::Integer(v) => Some(*v),
+        Value::Boolean(b) => Some(*b as i64),
+        _ => None,
+    }
+}
+
+/// A named set of control values, applied in `CameraControl` order.
+///
+/// Profiles live in a JSON file keyed by name:
+///
+/// ```json
+/// {
+///     "finger": { "auto_exposure": 1, "exposure_absolute": 150, "gain": 0 },
+///     "env": { "auto_exposure": 3, "auto_white_balance": 1 }
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ControlProfile {
+    pub values: BTreeMap<CameraControl, i64>,
+}
+
+impl ControlProfile {
+    pub fn load(path: impl AsRef<Path>, name: &str) -> Result<Self> {
+        let mut profiles = Self::load_all(path.as_ref())?;
+        profiles.remove(name).ok_or_else(|| {
+            Error::Config(format!(
+                "no camera profile `{name}` in {}",
+                path.as_ref().display()
+            ))
+        })
+    }
+
+    pub fn load_all(path: impl AsRef<Path>) -> Result<BTreeMap<String, Self>> {
+        let text = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&text)?)
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::Error, Result};
+
+/// What sysfs tells us about a V4L2 capture node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraInfo {
+    pub path: PathBuf,
+    /// V4L2 card name, e.g. "USB Camera: USB Camera"
+    pub card: String,
+    pub vendor_id: Option<String>,
+    pub product_id: Option<String>,
+    pub serial: Option<String>,
+    /// USB port the device hangs off, e.g. "1-1.2"
+    pub port: Option<String>,
+}
+
+fn read_attr(dir: &Path, name: &str) -> Option<String> {
+    let value = fs::read_to_string(dir.join(name)).ok()?;
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+// the video node links to the USB interface, the attributes live on the
+// device a level or two above it
+fn usb_device_dir(sys_dir: &Path) -> Option<PathBuf> {
+    let mut dir = fs::canonicalize(sys_dir.join("device")).ok()?;
+    for _ in 0..3 {
+        if dir.join("idVendor").exists() {
+            return Some(dir);
+        }
+        dir = dir.parent()?.to_path_buf();
+    }
+    None
+}
+
+/// All video capture nodes currently present, metadata nodes left out.
+pub fn list_cameras() -> Vec<CameraInfo> {
+    let Ok(entries) = fs::read_dir("/sys/class/video4linux") else {
+        return vec![];
+    };
+    let mut cameras = vec![];
+    for entry in entries.flatten() {
+        let sys_dir = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !name.starts_with("video") {
+            continue;
+        }
+        // uvc exposes a second node per camera for metadata, index 0 is the
+        // one that streams frames
+        if read_attr(&sys_dir, "index").is_some_and(|i| i != "0") {
+            continue;
+        }
+        let usb_dir = usb_device_dir(&sys_dir);
+        let usb_attr = |attr: &str| usb_dir.as_deref().and_then(|d| read_attr(d, attr));
+        cameras.push(CameraInfo {
+            path: Path::new("/dev").join(&name),
+            card: read_attr(&sys_dir, "name").unwrap_or_default(),
+            vendor_id: usb_attr("idVendor"),
+            product_id: usb_attr("idProduct"),
+            serial: usb_attr("serial"),
+            port: usb_dir
+                .as_deref()
+                .and_then(|d| d.file_name())
+                .map(|n| n.to_string_lossy().to_string()),
+        });
+    }
+    cameras.sort_by(|a, b| a.path.cmp(&b.path));
+    cameras
+}
+
+/// Attributes a camera has to match. Fields left out match anything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraIdentity {
+    pub vendor_id: Option<String>,
+    pub product_id: Option<String>,
+    pub serial: Option<String>,
+    /// substring of the V4L2 card name
+    pub card: Option<String>,
+    pub port: Option<String>,
+}
+
+impl CameraIdentity {
+    pub fn matches(&self, info: &CameraInfo) -> bool {
+        let same = |want: &Option<String>, have: &Option<String>| match want {
+            Some(want) => have
+                .as_deref()
+                .is_some_and(|h| h.eq_ignore_ascii_case(want)),
+            None => true,
+        };
+        same(&self.vendor_id, &info.vendor_id)
+            && same(&self.product_id, &info.product_id)
+            && same(&self.serial, &info.serial)
+            && same(&self.port, &info.port)
+            && self
+                .card
+                .as_ref()
+                .is_none_or(|c| info.card.contains(c.as_str()))
+    }
+
+    /// The single connected camera matching this identity.
+    pub fn find(&self) -> Result<CameraInfo> {
+        let mut found: Vec<_> = list_cameras()
+            .into_iter()
+            .filter(|info| self.matches(info))
+            .collect();
+        match found.len() {
+            1 => Ok(found.remove(0)),
+            0 => Err(Error::Config(format!("no camera matches {self:?}"))),
+            _ => Err(Error::Config(format!(
+                "{} cameras match {self:?}: {:?}",
+                found.len(),
+                found.iter().map(|f| &f.path).collect::<Vec<_>>()
+            ))),
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum CameraRole {
+    LeftFinger,
+    RightFinger,
+    Env,
+}
+
+/// Which physical camera plays which role, loaded from JSON:
+///
+/// ```json
+/// {
+///     "left_finger": { "serial": "SN0001" },
+///     "right_finger": { "serial": "SN0002" },
+///     "env": { "vendor_id": "046d", "product_id": "085e" }
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CameraMap {
+    pub roles: BTreeMap<CameraRole, CameraIdentity>,
+}
+
+impl CameraMap {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    pub fn identity(&self, role: CameraRole) -> Result<&CameraIdentity> {
+        self.roles
+            .get(&role)
+            .ok_or_else(|| Error::Config(format!("no camera configured for {role:?}")))
+    }
+
+    /// Device path of the camera currently playing `role`.
+    pub fn find(&self, role: CameraRole) -> Result<PathBuf> {
+        Ok(self.identity(role)?.find()?.path)
+    }
+}
@@ -0,0 +1,142 @@
+use std::fmt::Display;
+
+use v4l::{
+    frameinterval::FrameIntervalEnum, framesize::FrameSizeEnum, video::Capture, Device, FourCC,
+    Fraction,
+};
+
+use crate::{pixel_format::PixelFormat, Result};
+
+/// One format / size / frame rate combination a device can stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CameraMode {
+    pub fourcc: FourCC,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+}
+
+impl CameraMode {
+    /// Whether `Camera` can decode frames of this mode.
+    pub fn is_supported(&self) -> bool {
+        PixelFormat::from_fourcc(self.fourcc).is_some()
+    }
+}
+
+impl Display for CameraMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}x{}@{}",
+            self.fourcc, self.width, self.height, self.fps
+        )
+    }
+}
+
+/// How `Camera` picks a mode when the requested one is not offered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum NegotiationPolicy {
+    /// Only the exact width, height and fps.
+    #[default]
+    Exact,
+    /// Closest frame rate first, then closest resolution.
+    PreferFps,
+    /// Closest resolution first, then closest frame rate.
+    PreferResolution,
+}
+
+pub(crate) fn fraction_fps(fraction: Fraction) -> u32 {
+    (fraction.denominator as f64 / fraction.numerator.max(1) as f64).round() as u32
+}
+
+fn frame_sizes(size: FrameSizeEnum, width: u32, height: u32) -> Vec<(u32, u32)> {
+    match size {
+        FrameSizeEnum::Discrete(d) => vec![(d.width, d.height)],
+        // offering every step would be thousands of modes, the bounds and the
+        // requested size (when it fits) are enough to negotiate with
+        FrameSizeEnum::Stepwise(s) => {
+            let mut sizes = vec![(s.min_width, s.min_height), (s.max_width, s.max_height)];
+            let fits = |v: u32, min: u32, max: u32, step: u32| {
+                (min..=max).contains(&v) && (v - min) % step.max(1) == 0
+            };
+            if fits(width, s.min_width, s.max_width, s.step_width)
+                && fits(height, s.min_height, s.max_height, s.step_height)
+            {
+                sizes.push((width, height));
+            }
+            sizes
+        }
+    }
+}
+
+fn frame_rates(interval: FrameIntervalEnum, fps: u32) -> Vec<u32> {
+    match interval {
+        FrameIntervalEnum::Discrete(fraction) => vec![fraction_fps(fraction)],
+        FrameIntervalEnum::Stepwise(s) => {
+            // the longest interval is the lowest rate
+            let (lo, hi) = (fraction_fps(s.max), fraction_fps(s.min));
+            let mut rates = vec![lo, hi];
+            if (lo..=hi).contains(&fps) {
+                rates.push(fps);
+            }
+            rates
+        }
+    }
+}
+
+/// Every mode the device reports. `width`, `height` and `fps` are only used
+/// to pick a representative out of stepwise ranges.
+pub(crate) fn enum_modes(
+    device: &Device,
+    width: u32,
+    height: u32,
+    fps: u32,
+) -> Result<Vec<CameraMode>> {
+    let mut modes = vec![];
+    for format in device.enum_formats()? {
+        for frame_size in device.enum_framesizes(format.fourcc)? {
+            for (w, h) in frame_sizes(frame_size.size, width, height) {
+                for fi in device.enum_frameintervals(format.fourcc, w, h)? {
+                    for rate in frame_rates(fi.interval, fps) {
+                        let mode = CameraMode {
+                            fourcc: format.fourcc,
+                            width: w,
+                            height: h,
+                            fps: rate,
+                        };
+                        if !modes.contains(&mode) {
+                            modes.push(mode);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(modes)
+}
+
+/// Pick the best decodable mode for the request, MJPG breaking ties.
+pub(crate) fn choose_mode(
+    modes: &[CameraMode],
+    width: u32,
+    height: u32,
+    fps: u32,
+    policy: NegotiationPolicy,
+) -> Option<CameraMode> {
+    let size_diff = |m: &CameraMode| m.width.abs_diff(width) + m.height.abs_diff(height);
+    let fps_diff = |m: &CameraMode| m.fps.abs_diff(fps);
+    let not_mjpeg = |m: &CameraMode| m.fourcc != PixelFormat::Mjpeg.fourcc();
+    let candidates = modes.iter().filter(|m| m.is_supported());
+    match policy {
+        NegotiationPolicy::Exact => candidates
+            .filter(|m| size_diff(m) == 0 && fps_diff(m) == 0)
+            .min_by_key(|m| not_mjpeg(m)),
+        NegotiationPolicy::PreferFps => {
+            candidates.min_by_key(|m| (fps_diff(m), size_diff(m), not_mjpeg(m)))
+        }
+        NegotiationPolicy::PreferResolution => {
+            candidates.min_by_key(|m| (size_diff(m), fps_diff(m), not_mjpeg(m)))
+        }
+    }
+    .copied()
+}
@@ -0,0 +1,224 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{errors::Error, CameraEvent, FrameSource, FrameStats, GrayFrame, Result};
+
+// first pause after a failed capture, doubled while failures continue
+const ERROR_BACKOFF_MIN: Duration = Duration::from_millis(10);
+const ERROR_BACKOFF_MAX: Duration = Duration::from_secs(1);
+
+/// A JPEG frame grabbed by `CaptureThread`.
+#[derive(Debug, Clone)]
+pub struct CapturedFrame {
+    pub jpeg: Vec<u8>,
+    /// Capture time from the source, the same clock `capture` reports.
+    pub time_stamp: Duration,
+    /// When the frame was queued, after capture and any decoding.
+    pub received_at: Instant,
+    pub stats: FrameStats,
+    /// Luminance for marker detection, when spawned with `spawn_with_gray`.
+    pub gray: Option<GrayFrame>,
+}
+
+impl CapturedFrame {
+    /// Only the time spent in the queue, counted from `received_at`. Time
+    /// in the driver and decoding come before that and are not included,
+    /// `time_stamp` still tells when the frame was captured.
+    pub fn age(&self) -> Duration {
+        self.received_at.elapsed()
+    }
+}
+
+#[derive(Default)]
+struct Slot {
+    frames: VecDeque<CapturedFrame>,
+    events: Vec<CameraEvent>,
+    // frames pushed out of a full queue before anyone took them
+    overwritten: u64,
+    // frames whose luminance could not be decoded
+    decode_failed: u64,
+    finished: bool,
+}
+
+struct Shared {
+    slot: Mutex<Slot>,
+    ready: Condvar,
+    stop: AtomicBool,
+}
+
+/// Runs a `FrameSource` on its own thread so capture never waits for
+/// processing.
+///
+/// With a queue length of one only the latest frame is kept, older frames
+/// are dropped as soon as a newer one arrives. A longer queue keeps up to
+/// that many frames and drops the oldest when full.
+pub struct CaptureThread {
+    shared: Arc<Shared>,
+    handle: Option<JoinHandle<()>>,
+    width: u32,
+    height: u32,
+    fps: u32,
+}
+
+impl CaptureThread {
+    pub fn spawn(source: Box<dyn FrameSource + Send>, queue: usize) -> Self {
+        Self::start(source, queue, None)
+    }
+
+    /// Like `spawn`, but every frame also carries its luminance shrunk by
+    /// `scale`, decoded on the capture thread. Frames that fail to decode
+    /// are dropped and counted in `FrameStats::decode_failed`.
+    pub fn spawn_with_gray(source: Box<dyn FrameSource + Send>, queue: usize, scale: u32) -> Self {
+        Self::start(source, queue, Some(scale))
+    }
+
+    fn start(mut source: Box<dyn FrameSource + Send>, queue: usize, scale: Option<u32>) -> Self {
+        let queue = queue.max(1);
+        let (width, height, fps) = (source.width(), source.height(), source.fps());
+        let shared = Arc::new(Shared {
+            slot: Mutex::new(Slot::default()),
+            ready: Condvar::new(),
+            stop: AtomicBool::new(false),
+        });
+        let thread_shared = shared.clone();
+        let handle = thread::spawn(move || {
+            let shared = thread_shared;
+            let mut failures = 0u32;
+            while !shared.stop.load(Ordering::Relaxed) {
+                let mut gray = scale.map(GrayFrame::new);
+                let result = match gray.as_mut() {
+                    Some(gray) => source.capture_mjpeg_gray(gray),
+                    None => source.capture_mjpeg(),
+                };
+                let events = source.take_events();
+                let Ok(mut slot) = shared.slot.lock() else {
+                    return;
+                };
+                slot.events.extend(events);
+                match result {
+                    Ok((jpeg, time_stamp)) => {
+                        if slot.frames.len() == queue {
+                            slot.frames.pop_front();
+                            slot.overwritten += 1;
+                        }
+                        failures = 0;
+                        let mut stats = source.stats();
+                        stats.decode_failed += slot.decode_failed;
+                        slot.frames.push_back(CapturedFrame {
+                            jpeg,
+                            time_stamp,
+                            received_at: Instant::now(),
+                            stats,
+                            gray,
+                        });
+                    }
+                    Err(Error::EndOfStream) => {
+                        slot.finished = true;
+                        shared.ready.notify_all();
+                        return;
+                    }
+                    Err(Error::JPEGDecoder(_)) => {
+                        slot.decode_failed += 1;
+                        continue;
+                    }
+                    // a disconnected camera keeps retrying inside capture
+                    Err(Error::Disconnected) => continue,
+                    Err(e) => {
+                        failures += 1;
+                        let retry_in = ERROR_BACKOFF_MIN
+                            .saturating_mul(1 << (failures - 1).min(16))
+                            .min(ERROR_BACKOFF_MAX);
+                        slot.events.push(CameraEvent::CaptureFailed {
+                            failures,
+                            retry_in,
+                            reason: format!("{e:?}"),
+                        });
+                        drop(slot);
+                        thread::sleep(retry_in);
+                        continue;
+                    }
+                }
+                shared.ready.notify_all();
+            }
+        });
+        Self {
+            shared,
+            handle: Some(handle),
+            width,
+            height,
+            fps,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn fps(&self) -> u32 {
+        self.fps
+    }
+
+    /// Oldest frame still queued, waiting for one if none is. With a queue
+    /// length of one this is always the freshest frame.
+    pub fn recv(&self) -> Result<CapturedFrame> {
+        let mut slot = self.shared.slot.lock()?;
+        loop {
+            if let Some(frame) = slot.frames.pop_front() {
+                return Ok(frame);
+            }
+            if slot.finished {
+                return Err(Error::EndOfStream);
+            }
+            slot = self.shared.ready.wait(slot)?;
+        }
+    }
+
+    /// Like `recv`, but gives up after `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Option<CapturedFrame>> {
+        let deadline = Instant::now() + timeout;
+        let mut slot = self.shared.slot.lock()?;
+        loop {
+            if let Some(frame) = slot.frames.pop_front() {
+                return Ok(Some(frame));
+            }
+            if slot.finished {
+                return Err(Error::EndOfStream);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            slot = self.shared.ready.wait_timeout(slot, deadline - now)?.0;
+        }
+    }
+
+    /// Connection changes reported by the source since the last call.
+    pub fn take_events(&self) -> Result<Vec<CameraEvent>> {
+        Ok(std::mem::take(&mut self.shared.slot.lock()?.events))
+    }
+
+    /// Frames dropped because the consumer did not keep up.
+    pub fn overwritten(&self) -> Result<u64> {
+        Ok(self.shared.slot.lock()?.overwritten)
+    }
+}
+
+impl Drop for CaptureThread {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
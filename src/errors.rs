@@ -7,6 +7,17 @@ pub enum Error {
     OpenCV(opencv::Error),
     V4L2(io::Error),
     JPEGDecoder(zune_jpeg::errors::DecodeErrors),
+    Json(serde_json::Error),
+    TomlDe(toml::de::Error),
+    TomlSer(toml::ser::Error),
+    Recorder(burn::record::RecorderError),
+    Config(String),
+    NoCameraMode {
+        requested: String,
+        available: Vec<crate::CameraMode>,
+    },
+    EndOfStream,
+    Disconnected,
     ChannelSend,
     ChannelRecv,
     Lock,
@@ -30,6 +41,30 @@ impl From<zune_jpeg::errors::DecodeErrors> for Error {
     }
 }
 
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Error::Json(value)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(value: toml::de::Error) -> Self {
+        Error::TomlDe(value)
+    }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(value: toml::ser::Error) -> Self {
+        Error::TomlSer(value)
+    }
+}
+
+impl From<burn::record::RecorderError> for Error {
+    fn from(value: burn::record::RecorderError) -> Self {
+        Error::Recorder(value)
+    }
+}
+
 impl<T> From<PoisonError<T>> for Error {
     fn from(_: PoisonError<T>) -> Self {
         Error::Lock
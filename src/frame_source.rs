@@ -0,0 +1,502 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use opencv::{
+    core::{Mat, MatTraitConstManual, Vector, VectorToVec, CV_8UC3},
+    imgcodecs::imencode_def,
+    imgproc::{cvt_color_def, COLOR_BGR2RGB, COLOR_RGB2BGR},
+    videoio::{
+        VideoCapture, VideoCaptureTrait, VideoCaptureTraitConst, CAP_ANY, CAP_PROP_FPS,
+        CAP_PROP_FRAME_HEIGHT, CAP_PROP_FRAME_WIDTH, CAP_PROP_POS_FRAMES, CAP_PROP_POS_MSEC,
+    },
+};
+use serde::{Deserialize, Serialize};
+use zune_jpeg::JpegDecoder;
+
+use crate::{data_saver::FrameData, errors::Error, GrayFrame, Result};
+
+/// Anything that hands out camera frames together with their capture time.
+///
+/// `Camera` is the real V4L2 device; the other implementations replay
+/// recordings or generate frames so the pipelines can run without hardware.
+pub trait FrameSource {
+    fn width(&self) -> u32;
+    fn height(&self) -> u32;
+    fn fps(&self) -> u32;
+
+    /// Next frame as a JPEG encoded buffer.
+    fn capture_mjpeg(&mut self) -> Result<(Vec<u8>, Duration)>;
+
+    /// Next frame decoded into a packed RGB buffer (`width * height * 3`).
+    fn capture(&mut self) -> Result<(&[u8], Duration)>;
+
+    /// Next frame as JPEG with its luminance put into `gray`. Sources with
+    /// uncompressed frames take the luminance from those instead of decoding
+    /// the JPEG again.
+    fn capture_mjpeg_gray(&mut self, gray: &mut GrayFrame) -> Result<(Vec<u8>, Duration)> {
+        let (jpeg, time_stamp) = self.capture_mjpeg()?;
+        gray.decode_jpeg(&jpeg)?;
+        Ok((jpeg, time_stamp))
+    }
+
+    /// Connection changes since the last call, only live devices have any.
+    fn take_events(&mut self) -> Vec<CameraEvent> {
+        vec![]
+    }
+
+    /// Frame counters, only live devices keep them.
+    fn stats(&self) -> FrameStats {
+        FrameStats::default()
+    }
+}
+
+/// Per camera frame accounting, driven by the V4L2 buffer sequence number.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct FrameStats {
+    /// Sequence number of the latest frame.
+    pub sequence: Option<u32>,
+    pub delivered: u64,
+    /// Frames the driver counted but we never saw.
+    pub dropped: u64,
+    pub decode_failed: u64,
+}
+
+impl FrameStats {
+    // returns how many frames went missing before this one
+    pub(crate) fn observe(&mut self, sequence: u32) -> u32 {
+        let gap = match self.sequence {
+            // a huge gap is the counter restarting, not a loss
+            Some(last) => Some(sequence.wrapping_sub(last).wrapping_sub(1))
+                .filter(|gap| *gap < u32::MAX / 2)
+                .unwrap_or(0),
+            None => 0,
+        };
+        self.dropped += gap as u64;
+        self.sequence = Some(sequence);
+        gap
+    }
+}
+
+/// Frame counters published next to every frame.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrameInfo {
+    pub stats: FrameStats,
+    pub time_stamp: Duration,
+    /// Time the frame spent queued for processing, after capture and
+    /// decoding. Not the latency since capture, see `time_stamp` for that.
+    pub age: Duration,
+    /// Frames replaced by newer ones before they were processed.
+    pub skipped: u64,
+}
+
+impl FrameData for FrameInfo {
+    fn time_stamp(&self) -> Duration {
+        self.time_stamp
+    }
+}
+
+/// Reported by a `Camera` when its device goes away or comes back, and by
+/// `CaptureThread` when capturing fails for another reason.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CameraEvent {
+    Disconnected {
+        reason: String,
+    },
+    ReconnectFailed {
+        attempt: u32,
+        retry_in: Duration,
+        reason: String,
+    },
+    Reconnected {
+        attempts: u32,
+        downtime: Duration,
+    },
+    /// The control profile could not be applied again after a reconnect.
+    ProfileFailed {
+        reason: String,
+    },
+    CaptureFailed {
+        /// Failures in a row, including this one.
+        failures: u32,
+        retry_in: Duration,
+        reason: String,
+    },
+}
+
+/// Open a recording for replay: a directory is read as MJPEG frames, anything
+/// else is handed to OpenCV as a video file.
+pub fn open_replay(path: &str, fps: u32) -> Result<Box<dyn FrameSource + Send>> {
+    if Path::new(path).is_dir() {
+        Ok(Box::new(MjpegDirSource::new(path, fps)?))
+    } else {
+        Ok(Box::new(VideoFileSource::new(path)?))
+    }
+}
+
+// sleeps so that frames come out with the same spacing as their time stamps
+#[derive(Default)]
+struct Pacer {
+    origin: Option<(Instant, Duration)>,
+}
+
+impl Pacer {
+    fn wait(&mut self, time_stamp: Duration) {
+        let (start, first) = *self.origin.get_or_insert((Instant::now(), time_stamp));
+        let target = time_stamp.saturating_sub(first);
+        let elapsed = start.elapsed();
+        if target > elapsed {
+            thread::sleep(target - elapsed);
+        }
+    }
+}
+
+fn frame_period(fps: u32) -> Duration {
+    Duration::from_secs(1) / fps.max(1)
+}
+
+pub(crate) fn encode_rgb(
+    rgb: &[u8],
+    width: u32,
+    height: u32,
+    bgr_mat: &mut Mat,
+) -> Result<Vec<u8>> {
+    let rgb_img = unsafe {
+        Mat::new_rows_cols_with_data_unsafe_def(
+            height as i32,
+            width as i32,
+            CV_8UC3,
+            rgb.as_ptr() as *mut _,
+        )
+    }?;
+    cvt_color_def(&rgb_img, bgr_mat, COLOR_RGB2BGR)?;
+    let mut v = Vector::<u8>::new();
+    imencode_def(".jpg", &*bgr_mat, &mut v)?;
+    Ok(v.to_vec())
+}
+
+/// Replays a directory of JPEG frames (`*.jpg`, `*.jpeg`, `*.mjpeg`).
+///
+/// When every file stem is an integer it is taken as the capture time in
+/// microseconds and frames are replayed in that order, otherwise files are
+/// replayed by name and spaced `1 / fps` apart.
+pub struct MjpegDirSource {
+    frames: Vec<(PathBuf, Duration)>,
+    next: usize,
+    looping: bool,
+    realtime: bool,
+    pacer: Pacer,
+    loop_offset: Duration,
+    fps: u32,
+    width: u32,
+    height: u32,
+    rgb_buffer: Vec<u8>,
+}
+
+impl MjpegDirSource {
+    pub fn new(dir: impl AsRef<Path>, fps: u32) -> Result<Self> {
+        let mut paths = vec![];
+        for entry in fs::read_dir(dir.as_ref())? {
+            let path = entry?.path();
+            let is_jpeg = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| matches!(e.to_ascii_lowercase().as_str(), "jpg" | "jpeg" | "mjpeg"))
+                .unwrap_or(false);
+            if is_jpeg {
+                paths.push(path);
+            }
+        }
+        if paths.is_empty() {
+            return Err(std::io::Error::other(format!(
+                "no jpeg frames in {}",
+                dir.as_ref().display()
+            )))?;
+        }
+        paths.sort();
+
+        let stamps: Option<Vec<u64>> = paths
+            .iter()
+            .map(|p| p.file_stem()?.to_str()?.parse().ok())
+            .collect();
+        let mut frames: Vec<(PathBuf, Duration)> = match stamps {
+            Some(stamps) => paths
+                .into_iter()
+                .zip(stamps)
+                .map(|(p, us)| (p, Duration::from_micros(us)))
+                .collect(),
+            None => paths
+                .into_iter()
+                .enumerate()
+                .map(|(i, p)| (p, frame_period(fps) * i as u32))
+                .collect(),
+        };
+        frames.sort_by_key(|(_, t)| *t);
+
+        let first = fs::read(&frames[0].0)?;
+        let mut decoder = JpegDecoder::new(first.as_slice());
+        decoder.decode_headers()?;
+        let info = decoder.info().unwrap();
+        let (width, height) = (info.width as u32, info.height as u32);
+
+        Ok(Self {
+            frames,
+            next: 0,
+            looping: false,
+            realtime: true,
+            pacer: Pacer::default(),
+            loop_offset: Duration::ZERO,
+            fps,
+            width,
+            height,
+            rgb_buffer: vec![0u8; (width * height * 3) as usize],
+        })
+    }
+
+    /// Start over from the first frame instead of ending the stream.
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    /// Sleep between frames to honour the recorded time stamps (default),
+    /// or hand frames out as fast as they are asked for.
+    pub fn with_realtime(mut self, realtime: bool) -> Self {
+        self.realtime = realtime;
+        self
+    }
+
+    fn next_frame(&mut self) -> Result<(Vec<u8>, Duration)> {
+        if self.next == self.frames.len() {
+            if !self.looping {
+                return Err(Error::EndOfStream);
+            }
+            let last = self.frames[self.frames.len() - 1].1;
+            self.loop_offset += last - self.frames[0].1 + frame_period(self.fps);
+            self.next = 0;
+        }
+        let (path, time_stamp) = &self.frames[self.next];
+        self.next += 1;
+        let time_stamp = *time_stamp + self.loop_offset;
+        let data = fs::read(path)?;
+        if self.realtime {
+            self.pacer.wait(time_stamp);
+        }
+        Ok((data, time_stamp))
+    }
+}
+
+impl FrameSource for MjpegDirSource {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn fps(&self) -> u32 {
+        self.fps
+    }
+
+    fn capture_mjpeg(&mut self) -> Result<(Vec<u8>, Duration)> {
+        self.next_frame()
+    }
+
+    fn capture(&mut self) -> Result<(&[u8], Duration)> {
+        let (data, time_stamp) = self.next_frame()?;
+        let mut decoder = JpegDecoder::new(data.as_slice());
+        decoder.decode_into(&mut self.rgb_buffer)?;
+        Ok((&self.rgb_buffer, time_stamp))
+    }
+}
+
+/// Replays a recorded video file through OpenCV, using the container's
+/// presentation time as the frame time stamp.
+pub struct VideoFileSource {
+    capture: VideoCapture,
+    looping: bool,
+    realtime: bool,
+    pacer: Pacer,
+    loop_offset: Duration,
+    last_time_stamp: Duration,
+    fps: u32,
+    width: u32,
+    height: u32,
+    bgr_mat: Mat,
+    rgb_mat: Mat,
+}
+
+impl VideoFileSource {
+    pub fn new(path: &str) -> Result<Self> {
+        let capture = VideoCapture::from_file(path, CAP_ANY)?;
+        if !capture.is_opened()? {
+            return Err(std::io::Error::other(format!("can not open video:{path}")))?;
+        }
+        let width = capture.get(CAP_PROP_FRAME_WIDTH)? as u32;
+        let height = capture.get(CAP_PROP_FRAME_HEIGHT)? as u32;
+        let fps = capture.get(CAP_PROP_FPS)?.round() as u32;
+        Ok(Self {
+            capture,
+            looping: false,
+            realtime: true,
+            pacer: Pacer::default(),
+            loop_offset: Duration::ZERO,
+            last_time_stamp: Duration::ZERO,
+            fps,
+            width,
+            height,
+            bgr_mat: Mat::default(),
+            rgb_mat: Mat::default(),
+        })
+    }
+
+    /// Start over from the first frame instead of ending the stream.
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    /// Sleep between frames to honour the recorded time stamps (default),
+    /// or hand frames out as fast as they are asked for.
+    pub fn with_realtime(mut self, realtime: bool) -> Self {
+        self.realtime = realtime;
+        self
+    }
+
+    // reads the next frame into `bgr_mat`
+    fn next_frame(&mut self) -> Result<Duration> {
+        if !self.capture.read(&mut self.bgr_mat)? {
+            if !self.looping {
+                return Err(Error::EndOfStream);
+            }
+            self.loop_offset = self.last_time_stamp + frame_period(self.fps);
+            self.capture.set(CAP_PROP_POS_FRAMES, 0.)?;
+            if !self.capture.read(&mut self.bgr_mat)? {
+                return Err(Error::EndOfStream);
+            }
+        }
+        let msec = self.capture.get(CAP_PROP_POS_MSEC)?;
+        let time_stamp = Duration::from_secs_f64(msec.max(0.) / 1000.) + self.loop_offset;
+        self.last_time_stamp = time_stamp;
+        if self.realtime {
+            self.pacer.wait(time_stamp);
+        }
+        Ok(time_stamp)
+    }
+}
+
+impl FrameSource for VideoFileSource {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn fps(&self) -> u32 {
+        self.fps
+    }
+
+    fn capture_mjpeg(&mut self) -> Result<(Vec<u8>, Duration)> {
+        let time_stamp = self.next_frame()?;
+        let mut v = Vector::<u8>::new();
+        imencode_def(".jpg", &self.bgr_mat, &mut v)?;
+        Ok((v.to_vec(), time_stamp))
+    }
+
+    fn capture(&mut self) -> Result<(&[u8], Duration)> {
+        let time_stamp = self.next_frame()?;
+        cvt_color_def(&self.bgr_mat, &mut self.rgb_mat, COLOR_BGR2RGB)?;
+        Ok((self.rgb_mat.data_bytes()?, time_stamp))
+    }
+}
+
+/// Generates a gradient with a bright square circling the centre, paced at
+/// `fps` like a real camera.
+pub struct SyntheticSource {
+    fps: u32,
+    width: u32,
+    height: u32,
+    frame_index: u32,
+    realtime: bool,
+    pacer: Pacer,
+    rgb_buffer: Vec<u8>,
+    bgr_mat: Mat,
+}
+
+impl SyntheticSource {
+    pub fn new(width: u32, height: u32, fps: u32) -> Self {
+        Self {
+            fps,
+            width,
+            height,
+            frame_index: 0,
+            realtime: true,
+            pacer: Pacer::default(),
+            rgb_buffer: vec![0u8; (width * height * 3) as usize],
+            bgr_mat: Mat::default(),
+        }
+    }
+
+    /// Sleep to keep `fps` (default), or generate frames as fast as asked.
+    pub fn with_realtime(mut self, realtime: bool) -> Self {
+        self.realtime = realtime;
+        self
+    }
+
+    fn render(&mut self) -> Duration {
+        let time_stamp = frame_period(self.fps) * self.frame_index;
+        self.frame_index = self.frame_index.wrapping_add(1);
+
+        let (w, h) = (self.width as usize, self.height as usize);
+        let side = w.min(h) / 4;
+        let phase = time_stamp.as_secs_f64() * std::f64::consts::PI;
+        let cx = w as f64 / 2. + phase.cos() * w as f64 / 4.;
+        let cy = h as f64 / 2. + phase.sin() * h as f64 / 4.;
+        let x0 = (cx as usize).saturating_sub(side / 2);
+        let y0 = (cy as usize).saturating_sub(side / 2);
+        for (i, px) in self.rgb_buffer.chunks_exact_mut(3).enumerate() {
+            let (x, y) = (i % w, i / w);
+            if (x0..x0 + side).contains(&x) && (y0..y0 + side).contains(&y) {
+                px.copy_from_slice(&[255, 255, 255]);
+            } else {
+                px.copy_from_slice(&[(x * 255 / w) as u8, (y * 255 / h) as u8, 64]);
+            }
+        }
+
+        if self.realtime {
+            self.pacer.wait(time_stamp);
+        }
+        time_stamp
+    }
+}
+
+impl FrameSource for SyntheticSource {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn fps(&self) -> u32 {
+        self.fps
+    }
+
+    fn capture_mjpeg(&mut self) -> Result<(Vec<u8>, Duration)> {
+        let time_stamp = self.render();
+        let data = encode_rgb(&self.rgb_buffer, self.width, self.height, &mut self.bgr_mat)?;
+        Ok((data, time_stamp))
+    }
+
+    fn capture(&mut self) -> Result<(&[u8], Duration)> {
+        let time_stamp = self.render();
+        Ok((&self.rgb_buffer, time_stamp))
+    }
+}
@@ -1,17 +1,69 @@
 mod errors;
+pub use errors::Error;
 use errors::Result;
 use serde::{Deserialize, Serialize};
 
 mod usb_camera;
 pub use usb_camera::Camera;
 
+mod pixel_format;
+pub use pixel_format::{GrayFrame, PixelFormat};
+
+mod camera_mode;
+pub use camera_mode::{CameraMode, NegotiationPolicy};
+
+mod camera_identity;
+pub use camera_identity::{list_cameras, CameraIdentity, CameraInfo, CameraMap, CameraRole};
+
+mod camera_control;
+pub use camera_control::{CameraControl, ControlInfo, ControlProfile};
+
+mod frame_source;
+pub use frame_source::{
+    open_replay, CameraEvent, FrameInfo, FrameSource, FrameStats, MjpegDirSource, SyntheticSource,
+    VideoFileSource,
+};
+
+mod capture_thread;
+pub use capture_thread::{CaptureThread, CapturedFrame};
+
+mod aruco_params;
+pub use aruco_params::{ArucoDictionary, CornerRefinement, DetectorSetting};
+
+mod aruco_board;
+pub use aruco_board::{BoardMarker, BoardPose, BoardSetting};
+
 mod aruco_finder;
 pub use aruco_finder::{
     Aruco, ArucoFinder, ArucoFinderSetting, ArucoIntrinsic, CameraDistortion, CameraIntrinsic,
+    MarkerQuality,
 };
 
+mod marker_detector;
+pub use marker_detector::MarkerDetector;
+
+#[cfg(feature = "apriltag")]
+mod apriltag_finder;
+#[cfg(feature = "apriltag")]
+pub use apriltag_finder::{AprilTagFamily, AprilTagFinder};
+
+mod roi_detector;
+pub use roi_detector::{DetectionInfo, RoiDetector, RoiSetting};
+
+mod calibration;
+pub use calibration::{CalibrationPattern, Calibrator, CameraCalibration};
+
+mod marker_tracker;
+pub use marker_tracker::{MarkerTracker, OneEuroSetting, TrackedAruco, TrackerSetting};
+
+mod rest_pose;
+pub use rest_pose::{MarkerDisplacement, RestPose, RestPoseCapture};
+
 mod soft_finger;
-pub use soft_finger::{FingerForceData, Force, SoftFinger};
+pub use soft_finger::{
+    Activation, FingerContext, FingerForceData, Force, InputFeatures, LayerConfig, ModelMetadata,
+    NetConfig, SoftFinger,
+};
 
 mod data_saver;
 pub use data_saver::{CSVFile, Command, DataFile};
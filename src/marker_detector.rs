@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use opencv::core::{Mat, MatTraitConst, Point2f, Rect, Vector};
+
+use crate::{Aruco, ArucoFinder, Result};
+
+/// Anything that finds square fiducials in a gray frame and estimates their
+/// pose, so detectors can be swapped and compared on the same recordings.
+pub trait MarkerDetector {
+    /// Markers in a CV_8UC1 image shrunk by `scale`. Corners and poses are
+    /// at full resolution.
+    fn find_markers(
+        &mut self,
+        gray: &Mat,
+        scale: u32,
+        time_stamp: Duration,
+        arucos: &mut Vec<Aruco>,
+    ) -> Result<()> {
+        let roi = Rect::new(0, 0, gray.cols(), gray.rows());
+        self.find_markers_in(gray, roi, scale, time_stamp, arucos)
+    }
+
+    /// Like `find_markers`, also handing out the candidates that were
+    /// rejected as markers. Detectors that do not report them leave
+    /// `rejected` empty.
+    fn find_markers_with_rejected(
+        &mut self,
+        gray: &Mat,
+        scale: u32,
+        time_stamp: Duration,
+        arucos: &mut Vec<Aruco>,
+        rejected: &mut Vector<Vector<Point2f>>,
+    ) -> Result<()> {
+        rejected.clear();
+        self.find_markers(gray, scale, time_stamp, arucos)
+    }
+
+    /// Like `find_markers`, searching only `roi` of the shrunk image.
+    fn find_markers_in(
+        &mut self,
+        gray: &Mat,
+        roi: Rect,
+        scale: u32,
+        time_stamp: Duration,
+        arucos: &mut Vec<Aruco>,
+    ) -> Result<()>;
+}
+
+impl MarkerDetector for ArucoFinder {
+    fn find_markers(
+        &mut self,
+        gray: &Mat,
+        scale: u32,
+        time_stamp: Duration,
+        arucos: &mut Vec<Aruco>,
+    ) -> Result<()> {
+        self.find_scaled(gray, scale, time_stamp, arucos)
+    }
+
+    fn find_markers_with_rejected(
+        &mut self,
+        gray: &Mat,
+        scale: u32,
+        time_stamp: Duration,
+        arucos: &mut Vec<Aruco>,
+        rejected: &mut Vector<Vector<Point2f>>,
+    ) -> Result<()> {
+        self.find_with_rejected(gray, scale, time_stamp, arucos, Some(rejected))
+    }
+
+    fn find_markers_in(
+        &mut self,
+        gray: &Mat,
+        roi: Rect,
+        scale: u32,
+        time_stamp: Duration,
+        arucos: &mut Vec<Aruco>,
+    ) -> Result<()> {
+        self.find_in(gray, roi, scale, time_stamp, arucos)
+    }
+}
@@ -0,0 +1,246 @@
+use std::{collections::BTreeMap, f64::consts::TAU, time::Duration};
+
+use nalgebra::{Quaternion, UnitQuaternion};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aruco_finder::{euler_angles, rotation_rows},
+    Aruco,
+};
+
+/// One-Euro filter parameters. `min_cutoff` (Hz) sets the smoothing at rest,
+/// `beta` how quickly the cutoff opens up with speed, trading jitter for lag.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OneEuroSetting {
+    pub min_cutoff: f64,
+    pub beta: f64,
+    pub d_cutoff: f64,
+}
+
+impl OneEuroSetting {
+    pub fn new(min_cutoff: f64, beta: f64) -> Self {
+        Self {
+            min_cutoff,
+            beta,
+            d_cutoff: 1.0,
+        }
+    }
+}
+
+/// Filters per quantity, their units differ so they are tuned apart.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackerSetting {
+    /// Corners in pixels.
+    pub corners: OneEuroSetting,
+    /// Translation in meters.
+    pub translation: OneEuroSetting,
+    /// Rotation, as the components of a unit quaternion.
+    pub rotation: OneEuroSetting,
+    /// How long a marker that is no longer detected keeps being predicted.
+    pub max_prediction: Duration,
+}
+
+impl Default for TrackerSetting {
+    fn default() -> Self {
+        Self {
+            corners: OneEuroSetting::new(1.0, 0.05),
+            translation: OneEuroSetting::new(1.0, 5.0),
+            rotation: OneEuroSetting::new(1.0, 0.5),
+            max_prediction: Duration::from_millis(100),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct OneEuro {
+    // filtered value and its filtered rate of change per second
+    state: Option<(f64, f64)>,
+}
+
+impl OneEuro {
+    fn alpha(cutoff: f64, dt: f64) -> f64 {
+        let tau = 1.0 / (TAU * cutoff);
+        1.0 / (1.0 + tau / dt)
+    }
+
+    fn filter(&mut self, setting: &OneEuroSetting, x: f64, dt: f64) -> f64 {
+        let (value, rate) = match self.state {
+            Some((prev, prev_rate)) if dt > 0. => {
+                let a_d = Self::alpha(setting.d_cutoff, dt);
+                let rate = prev_rate + a_d * ((x - prev) / dt - prev_rate);
+                let cutoff = setting.min_cutoff + setting.beta * rate.abs();
+                let a = Self::alpha(cutoff, dt);
+                (prev + a * (x - prev), rate)
+            }
+            Some(state) => state,
+            None => (x, 0.),
+        };
+        self.state = Some((value, rate));
+        value
+    }
+
+    fn value(&self) -> f64 {
+        self.state.map_or(0., |(value, _)| value)
+    }
+
+    fn predict(&self, dt: f64) -> f64 {
+        self.state.map_or(0., |(value, rate)| value + rate * dt)
+    }
+}
+
+// 8 corner coordinates, 3 translations, 4 quaternion components
+const CHANNELS: usize = 15;
+
+struct Track {
+    filters: [OneEuro; CHANNELS],
+    last: Aruco,
+    last_measured: Duration,
+}
+
+fn channels(aruco: &Aruco) -> [f64; CHANNELS] {
+    let mut values = [0.; CHANNELS];
+    for (i, corner) in aruco.corners.iter().enumerate() {
+        values[i * 2] = corner[0] as f64;
+        values[i * 2 + 1] = corner[1] as f64;
+    }
+    values[8..11].copy_from_slice(&aruco.trans);
+    values[11..].copy_from_slice(&aruco.quaternion);
+    values
+}
+
+// the filtered quaternion is normalized again and the other rotation forms
+// are derived from it
+fn set_channels(aruco: &mut Aruco, values: &[f64; CHANNELS]) {
+    for (i, corner) in aruco.corners.iter_mut().enumerate() {
+        *corner = [values[i * 2] as f32, values[i * 2 + 1] as f32];
+    }
+    aruco.trans.copy_from_slice(&values[8..11]);
+    let [x, y, z, w] = [values[11], values[12], values[13], values[14]];
+    let q = Quaternion::new(w, x, y, z);
+    if q.norm() > f64::EPSILON {
+        let q = UnitQuaternion::from_quaternion(q);
+        let rotation = q.to_rotation_matrix();
+        aruco.quaternion = [q.i, q.j, q.k, q.w];
+        aruco.rotation = rotation_rows(&rotation);
+        aruco.euler_angles = euler_angles(&rotation);
+    }
+}
+
+impl Track {
+    fn new(aruco: &Aruco) -> Self {
+        Self {
+            filters: [OneEuro::default(); CHANNELS],
+            last: *aruco,
+            last_measured: aruco.time_stamp,
+        }
+    }
+
+    fn update(&mut self, setting: &TrackerSetting, aruco: &Aruco) {
+        let dt = aruco
+            .time_stamp
+            .saturating_sub(self.last_measured)
+            .as_secs_f64();
+        let mut values = channels(aruco);
+        // q and -q are the same rotation, stay on the side of the last one
+        let dot: f64 = self.filters[11..]
+            .iter()
+            .zip(&values[11..])
+            .map(|(filter, value)| filter.value() * value)
+            .sum();
+        if dot < 0. {
+            values[11..].iter_mut().for_each(|v| *v = -*v);
+        }
+        for (i, (filter, value)) in self.filters.iter_mut().zip(values.iter_mut()).enumerate() {
+            let filter_setting = match i {
+                0..=7 => &setting.corners,
+                8..=10 => &setting.translation,
+                _ => &setting.rotation,
+            };
+            *value = filter.filter(filter_setting, *value, dt);
+        }
+        let mut filtered = *aruco;
+        set_channels(&mut filtered, &values);
+        self.last = filtered;
+        self.last_measured = aruco.time_stamp;
+    }
+
+    fn predict(&self, time_stamp: Duration) -> Aruco {
+        let dt = time_stamp.saturating_sub(self.last_measured).as_secs_f64();
+        let mut values = [0.; CHANNELS];
+        for (value, filter) in values.iter_mut().zip(self.filters.iter()) {
+            *value = filter.predict(dt);
+        }
+        let mut predicted = self.last;
+        set_channels(&mut predicted, &values);
+        predicted.time_stamp = time_stamp;
+        predicted
+    }
+}
+
+/// A filtered marker, or one carried forward while it is not detected.
+#[derive(Debug, Clone, Copy)]
+pub struct TrackedAruco {
+    pub aruco: Aruco,
+    /// Extrapolated from earlier frames rather than measured in this one.
+    pub predicted: bool,
+    /// Time since the marker was last detected, zero when measured.
+    pub since_measured: Duration,
+}
+
+/// Follows markers by ID across frames, smoothing corners and pose with a
+/// One-Euro filter and bridging short detection gaps.
+pub struct MarkerTracker {
+    setting: TrackerSetting,
+    tracks: BTreeMap<i32, Track>,
+}
+
+impl MarkerTracker {
+    pub fn new(setting: TrackerSetting) -> Self {
+        Self {
+            setting,
+            tracks: BTreeMap::new(),
+        }
+    }
+
+    /// Feed the markers found in the frame taken at `time_stamp`. `tracked`
+    /// gets every marker in ID order, measured or predicted.
+    pub fn update(
+        &mut self,
+        arucos: &[Aruco],
+        time_stamp: Duration,
+        tracked: &mut Vec<TrackedAruco>,
+    ) {
+        tracked.clear();
+        for aruco in arucos {
+            let track = self
+                .tracks
+                .entry(aruco.id)
+                .or_insert_with(|| Track::new(aruco));
+            track.update(&self.setting, aruco);
+        }
+        let max_prediction = self.setting.max_prediction;
+        self.tracks
+            .retain(|_, track| time_stamp.saturating_sub(track.last_measured) <= max_prediction);
+        for (id, track) in &self.tracks {
+            if arucos.iter().any(|a| a.id == *id) {
+                tracked.push(TrackedAruco {
+                    aruco: track.last,
+                    predicted: false,
+                    since_measured: Duration::ZERO,
+                });
+            } else {
+                tracked.push(TrackedAruco {
+                    aruco: track.predict(time_stamp),
+                    predicted: true,
+                    since_measured: time_stamp.saturating_sub(track.last_measured),
+                });
+            }
+        }
+    }
+
+    /// Forget every marker, e.g. after the camera reconnected.
+    pub fn reset(&mut self) {
+        self.tracks.clear();
+    }
+}
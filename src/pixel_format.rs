@@ -0,0 +1,269 @@
+use v4l::FourCC;
+use zune_jpeg::{
+    zune_core::{colorspace::ColorSpace, options::DecoderOptions},
+    JpegDecoder,
+};
+
+use crate::Result;
+
+/// Pixel formats `Camera` knows how to turn into RGB or gray frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Mjpeg,
+    // packed 4:2:2, Y0 U Y1 V
+    Yuyv,
+    // planar Y followed by interleaved UV at half resolution
+    Nv12,
+    Grey,
+}
+
+impl PixelFormat {
+    pub fn from_fourcc(fourcc: FourCC) -> Option<Self> {
+        match &fourcc.repr {
+            b"MJPG" => Some(Self::Mjpeg),
+            b"YUYV" => Some(Self::Yuyv),
+            b"NV12" => Some(Self::Nv12),
+            b"GREY" => Some(Self::Grey),
+            _ => None,
+        }
+    }
+
+    pub fn fourcc(&self) -> FourCC {
+        match self {
+            Self::Mjpeg => FourCC::new(b"MJPG"),
+            Self::Yuyv => FourCC::new(b"YUYV"),
+            Self::Nv12 => FourCC::new(b"NV12"),
+            Self::Grey => FourCC::new(b"GREY"),
+        }
+    }
+
+    // bytes per line when the driver does not report a stride
+    pub(crate) fn min_stride(&self, width: u32) -> u32 {
+        match self {
+            Self::Yuyv => width * 2,
+            // an odd width still gets a whole UV pair for the last pixel
+            Self::Nv12 => (width + 1) & !1,
+            Self::Mjpeg | Self::Grey => width,
+        }
+    }
+}
+
+// BT.601 limited range
+#[inline]
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let c = 298 * (y as i32 - 16);
+    let d = u as i32 - 128;
+    let e = v as i32 - 128;
+    let clamp = |x: i32| ((x + 128) >> 8).clamp(0, 255) as u8;
+    [
+        clamp(c + 409 * e),
+        clamp(c - 100 * d - 208 * e),
+        clamp(c + 516 * d),
+    ]
+}
+
+pub(crate) fn yuyv_to_rgb(src: &[u8], width: usize, height: usize, stride: usize, rgb: &mut [u8]) {
+    for row in 0..height {
+        let line = &src[row * stride..row * stride + width * 2];
+        let out = &mut rgb[row * width * 3..(row + 1) * width * 3];
+        for (yuyv, px) in line.chunks_exact(4).zip(out.chunks_exact_mut(6)) {
+            let (y0, u, y1, v) = (yuyv[0], yuyv[1], yuyv[2], yuyv[3]);
+            px[..3].copy_from_slice(&yuv_to_rgb(y0, u, v));
+            px[3..].copy_from_slice(&yuv_to_rgb(y1, u, v));
+        }
+    }
+}
+
+pub(crate) fn nv12_to_rgb(src: &[u8], width: usize, height: usize, stride: usize, rgb: &mut [u8]) {
+    let (luma, chroma) = src.split_at(stride * height);
+    let uv_width = (width + 1) & !1;
+    for row in 0..height {
+        let y_line = &luma[row * stride..row * stride + width];
+        let uv_line = &chroma[(row / 2) * stride..(row / 2) * stride + uv_width];
+        let out = &mut rgb[row * width * 3..(row + 1) * width * 3];
+        for (col, px) in out.chunks_exact_mut(3).enumerate() {
+            let uv = col & !1;
+            px.copy_from_slice(&yuv_to_rgb(y_line[col], uv_line[uv], uv_line[uv + 1]));
+        }
+    }
+}
+
+pub(crate) fn grey_to_rgb(src: &[u8], width: usize, height: usize, stride: usize, rgb: &mut [u8]) {
+    for row in 0..height {
+        let line = &src[row * stride..row * stride + width];
+        let out = &mut rgb[row * width * 3..(row + 1) * width * 3];
+        for (g, px) in line.iter().zip(out.chunks_exact_mut(3)) {
+            px.copy_from_slice(&[*g, *g, *g]);
+        }
+    }
+}
+
+pub(crate) fn yuyv_to_gray(
+    src: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    gray: &mut [u8],
+) {
+    for row in 0..height {
+        let line = &src[row * stride..row * stride + width * 2];
+        let out = &mut gray[row * width..(row + 1) * width];
+        for (y, g) in line.iter().step_by(2).zip(out.iter_mut()) {
+            *g = *y;
+        }
+    }
+}
+
+// also covers the luma plane of NV12
+pub(crate) fn grey_to_gray(
+    src: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    gray: &mut [u8],
+) {
+    for row in 0..height {
+        gray[row * width..(row + 1) * width]
+            .copy_from_slice(&src[row * stride..row * stride + width]);
+    }
+}
+
+/// 8 bit luminance frames for marker detection, optionally shrunk by an
+/// integer factor. Each output pixel averages a `scale x scale` block.
+#[derive(Debug, Clone)]
+pub struct GrayFrame {
+    scale: u32,
+    width: u32,
+    height: u32,
+    full: Vec<u8>,
+    scaled: Vec<u8>,
+}
+
+impl GrayFrame {
+    pub fn new(scale: u32) -> Self {
+        Self {
+            scale: scale.max(1),
+            width: 0,
+            height: 0,
+            full: vec![],
+            scaled: vec![],
+        }
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// Width of the frame handed out, after scaling.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the frame handed out, after scaling.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The frame handed out last, `width * height` bytes.
+    pub fn data(&self) -> &[u8] {
+        if self.scale == 1 {
+            &self.full
+        } else {
+            &self.scaled
+        }
+    }
+
+    /// Decode only the luminance of a JPEG, no color conversion is done.
+    pub fn decode_jpeg(&mut self, jpeg: &[u8]) -> Result<&[u8]> {
+        let options = DecoderOptions::default().jpeg_set_out_colorspace(ColorSpace::Luma);
+        let mut decoder = JpegDecoder::new_with_options(jpeg, options);
+        decoder.decode_headers()?;
+        let info = decoder.info().unwrap();
+        let (w, h) = (info.width as usize, info.height as usize);
+        self.full.resize(w * h, 0);
+        decoder.decode_into(&mut self.full)?;
+        Ok(self.finish(w, h))
+    }
+
+    /// Luminance of a packed RGB frame.
+    pub fn convert_rgb(&mut self, rgb: &[u8], width: u32, height: u32) -> &[u8] {
+        let (w, h) = (width as usize, height as usize);
+        self.full.resize(w * h, 0);
+        for (px, g) in rgb.chunks_exact(3).zip(self.full.iter_mut()) {
+            let (r, gr, b) = (px[0] as u32, px[1] as u32, px[2] as u32);
+            *g = ((77 * r + 150 * gr + 29 * b) >> 8) as u8;
+        }
+        self.finish(w, h)
+    }
+
+    // `raw` is a frame straight from the driver
+    pub(crate) fn convert(
+        &mut self,
+        format: PixelFormat,
+        raw: &[u8],
+        width: usize,
+        height: usize,
+        stride: usize,
+    ) -> Result<&[u8]> {
+        if format == PixelFormat::Mjpeg {
+            return self.decode_jpeg(raw);
+        }
+        self.full.resize(width * height, 0);
+        match format {
+            PixelFormat::Yuyv => yuyv_to_gray(raw, width, height, stride, &mut self.full),
+            _ => grey_to_gray(raw, width, height, stride, &mut self.full),
+        }
+        Ok(self.finish(width, height))
+    }
+
+    fn finish(&mut self, width: usize, height: usize) -> &[u8] {
+        let s = self.scale as usize;
+        if s == 1 {
+            (self.width, self.height) = (width as u32, height as u32);
+            return &self.full;
+        }
+        let (w, h) = (width / s, height / s);
+        (self.width, self.height) = (w as u32, h as u32);
+        self.scaled.resize(w * h, 0);
+        let area = (s * s) as u32;
+        for (y, out) in self.scaled.chunks_exact_mut(w.max(1)).enumerate().take(h) {
+            for (x, g) in out.iter_mut().enumerate() {
+                let mut sum = 0u32;
+                for line in self.full[y * s * width..].chunks(width).take(s) {
+                    sum += line[x * s..x * s + s]
+                        .iter()
+                        .map(|v| *v as u32)
+                        .sum::<u32>();
+                }
+                *g = (sum / area) as u8;
+            }
+        }
+        &self.scaled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nv12_odd_width() {
+        // 3x2, lines padded to 4 bytes, one UV line with two pairs
+        #[rustfmt::skip]
+        let src = [
+            16, 235, 16, 0,
+            235, 16, 235, 0,
+            128, 128, 128, 255,
+        ];
+        let stride = PixelFormat::Nv12.min_stride(3) as usize;
+        assert_eq!(stride, 4);
+        let mut rgb = [0u8; 3 * 2 * 3];
+        nv12_to_rgb(&src, 3, 2, stride, &mut rgb);
+        #[rustfmt::skip]
+        let expected = [
+            0, 0, 0, 255, 255, 255, 203, 0, 0,
+            255, 255, 255, 0, 0, 0, 255, 152, 255,
+        ];
+        assert_eq!(rgb, expected);
+    }
+}
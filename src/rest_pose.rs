@@ -0,0 +1,139 @@
+use std::{fs, path::Path, time::Duration};
+
+use nalgebra::{Quaternion, UnitQuaternion};
+use serde::{Deserialize, Serialize};
+
+use crate::{Aruco, Result};
+
+/// Marker pose with the finger at rest, the zero every displacement is
+/// measured from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RestPose {
+    pub id: i32,
+    pub corners: [[f32; 2]; 4],
+    pub trans: [f64; 3],
+    /// `[x, y, z, w]`, like `Aruco::quaternion`.
+    pub quaternion: [f64; 4],
+    /// Frames averaged into the baseline.
+    pub frames: usize,
+}
+
+impl RestPose {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// How far `aruco` moved from rest, `None` for a different marker.
+    pub fn displacement(&self, aruco: &Aruco) -> Option<MarkerDisplacement> {
+        if aruco.id != self.id {
+            return None;
+        }
+        let mut corners = [[0.; 2]; 4];
+        for (d, (c, r)) in corners
+            .iter_mut()
+            .zip(aruco.corners.iter().zip(self.corners.iter()))
+        {
+            *d = [c[0] - r[0], c[1] - r[1]];
+        }
+        let trans = [
+            aruco.trans[0] - self.trans[0],
+            aruco.trans[1] - self.trans[1],
+            aruco.trans[2] - self.trans[2],
+        ];
+        let relative =
+            unit_quaternion(&self.quaternion).inverse() * unit_quaternion(&aruco.quaternion);
+        let rotation = relative.scaled_axis();
+        Some(MarkerDisplacement {
+            id: aruco.id,
+            corners,
+            trans,
+            rotation: [rotation.x, rotation.y, rotation.z],
+            time_stamp: aruco.time_stamp,
+        })
+    }
+}
+
+fn unit_quaternion(q: &[f64; 4]) -> UnitQuaternion<f64> {
+    UnitQuaternion::from_quaternion(Quaternion::new(q[3], q[0], q[1], q[2]))
+}
+
+/// A detection relative to its `RestPose`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarkerDisplacement {
+    pub id: i32,
+    /// Corner shift in pixels.
+    pub corners: [[f32; 2]; 4],
+    /// Translation shift in meters, camera frame.
+    pub trans: [f64; 3],
+    /// Rotation from rest as axis times angle in radians, marker frame at rest.
+    pub rotation: [f64; 3],
+    pub time_stamp: Duration,
+}
+
+/// Averages the pose of one marker over a number of frames with the finger
+/// unloaded.
+pub struct RestPoseCapture {
+    frames: usize,
+    id: Option<i32>,
+    corners: [[f64; 2]; 4],
+    trans: [f64; 3],
+    quaternion: Quaternion<f64>,
+    count: usize,
+}
+
+impl RestPoseCapture {
+    pub fn new(frames: usize) -> Self {
+        Self {
+            frames: frames.max(1),
+            id: None,
+            corners: [[0.; 2]; 4],
+            trans: [0.; 3],
+            quaternion: Quaternion::identity(),
+            count: 0,
+        }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.frames.saturating_sub(self.count)
+    }
+
+    /// Add one detection, the marker seen first is the one averaged and
+    /// others are ignored. Returns the baseline once enough frames are in.
+    pub fn add(&mut self, aruco: &Aruco) -> Option<RestPose> {
+        if *self.id.get_or_insert(aruco.id) != aruco.id {
+            return None;
+        }
+        for (sum, c) in self.corners.iter_mut().zip(aruco.corners.iter()) {
+            sum[0] += c[0] as f64;
+            sum[1] += c[1] as f64;
+        }
+        for (sum, t) in self.trans.iter_mut().zip(aruco.trans.iter()) {
+            *sum += t;
+        }
+        let q = *unit_quaternion(&aruco.quaternion).quaternion();
+        if self.count == 0 {
+            self.quaternion = q;
+        } else {
+            // q and -q are the same rotation, keep them on one side
+            self.quaternion += if self.quaternion.dot(&q) < 0. { -q } else { q };
+        }
+        self.count += 1;
+        if self.count < self.frames {
+            return None;
+        }
+        let n = self.count as f64;
+        let q = UnitQuaternion::from_quaternion(self.quaternion);
+        Some(RestPose {
+            id: aruco.id,
+            corners: self.corners.map(|c| [(c[0] / n) as f32, (c[1] / n) as f32]),
+            trans: self.trans.map(|t| t / n),
+            quaternion: [q.i, q.j, q.k, q.w],
+            frames: self.count,
+        })
+    }
+}
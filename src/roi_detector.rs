@@ -0,0 +1,204 @@
+use std::time::{Duration, Instant};
+
+use opencv::core::{Mat, MatTraitConst, Point2f, Rect, Vector};
+use serde::{Deserialize, Serialize};
+
+use crate::{Aruco, MarkerDetector, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoiSetting {
+    /// Margin around the last markers as a fraction of their size.
+    pub padding: f32,
+    /// The margin never shrinks below this many full resolution pixels.
+    pub min_padding: f32,
+    /// Search the whole frame at least every this many frames, 0 only on a
+    /// miss.
+    pub full_frame_every: u32,
+}
+
+impl Default for RoiSetting {
+    fn default() -> Self {
+        Self {
+            padding: 0.5,
+            min_padding: 24.,
+            full_frame_every: 30,
+        }
+    }
+}
+
+/// How one frame was searched, published next to the detections.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetectionInfo {
+    pub time_stamp: Duration,
+    /// Time spent in detection and pose estimation.
+    pub latency: Duration,
+    /// Window `[x, y, width, height]` at full resolution, `None` when the
+    /// whole frame was searched.
+    pub roi: Option<[i32; 4]>,
+    pub markers: usize,
+}
+
+/// Searches a padded window around the markers of the last frame and falls
+/// back to the whole frame on a miss or every `full_frame_every` frames.
+pub struct RoiDetector {
+    setting: RoiSetting,
+    // x0, y0, x1, y1 at full resolution
+    window: Option<[f32; 4]>,
+    since_full: u32,
+}
+
+impl RoiDetector {
+    pub fn new(setting: RoiSetting) -> Self {
+        Self {
+            setting,
+            window: None,
+            since_full: 0,
+        }
+    }
+
+    /// Like `MarkerDetector::find_markers`, reporting how the frame was
+    /// searched. `rejected` gets the rejected candidates of a full frame
+    /// search and is left empty when only the window was searched.
+    pub fn find(
+        &mut self,
+        detector: &mut dyn MarkerDetector,
+        gray: &Mat,
+        scale: u32,
+        time_stamp: Duration,
+        arucos: &mut Vec<Aruco>,
+        mut rejected: Option<&mut Vector<Vector<Point2f>>>,
+    ) -> Result<DetectionInfo> {
+        let start = Instant::now();
+        if let Some(rejected) = rejected.as_deref_mut() {
+            rejected.clear();
+        }
+        let scale = scale.max(1);
+        let every = self.setting.full_frame_every;
+        let roi = self
+            .window
+            .filter(|_| every == 0 || self.since_full < every)
+            .and_then(|window| to_rect(window, scale, gray.cols(), gray.rows()));
+        let mut searched = None;
+        if let Some(roi) = roi {
+            detector.find_markers_in(gray, roi, scale, time_stamp, arucos)?;
+            if !arucos.is_empty() {
+                let s = scale as i32;
+                searched = Some([roi.x * s, roi.y * s, roi.width * s, roi.height * s]);
+            }
+        }
+        if searched.is_some() {
+            self.since_full += 1;
+        } else {
+            match rejected {
+                Some(rejected) => detector
+                    .find_markers_with_rejected(gray, scale, time_stamp, arucos, rejected)?,
+                None => detector.find_markers(gray, scale, time_stamp, arucos)?,
+            }
+            self.since_full = 0;
+        }
+        self.window = window(arucos, &self.setting);
+        Ok(DetectionInfo {
+            time_stamp,
+            latency: start.elapsed(),
+            roi: searched,
+            markers: arucos.len(),
+        })
+    }
+
+    /// Forget the last window, the next frame is searched in full.
+    pub fn reset(&mut self) {
+        self.window = None;
+    }
+}
+
+// padded bounding box of every marker
+fn window(arucos: &[Aruco], setting: &RoiSetting) -> Option<[f32; 4]> {
+    let mut corners = arucos.iter().flat_map(|a| a.corners.iter());
+    let first = corners.next()?;
+    let mut bounds = [first[0], first[1], first[0], first[1]];
+    for c in corners {
+        bounds[0] = bounds[0].min(c[0]);
+        bounds[1] = bounds[1].min(c[1]);
+        bounds[2] = bounds[2].max(c[0]);
+        bounds[3] = bounds[3].max(c[1]);
+    }
+    let size = (bounds[2] - bounds[0]).max(bounds[3] - bounds[1]);
+    let pad = (size * setting.padding).max(setting.min_padding);
+    Some([
+        bounds[0] - pad,
+        bounds[1] - pad,
+        bounds[2] + pad,
+        bounds[3] + pad,
+    ])
+}
+
+// window in the shrunk image, clipped to it
+fn to_rect(window: [f32; 4], scale: u32, cols: i32, rows: i32) -> Option<Rect> {
+    let scale = scale as f32;
+    let x0 = ((window[0] / scale).floor() as i32).clamp(0, cols);
+    let y0 = ((window[1] / scale).floor() as i32).clamp(0, rows);
+    let x1 = ((window[2] / scale).ceil() as i32).clamp(0, cols);
+    let y1 = ((window[3] / scale).ceil() as i32).clamp(0, rows);
+    (x1 > x0 && y1 > y0).then(|| Rect::new(x0, y0, x1 - x0, y1 - y0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MarkerQuality;
+
+    fn aruco(corners: [[f32; 2]; 4]) -> Aruco {
+        Aruco {
+            id: 0,
+            corners,
+            trans: [0.; 3],
+            euler_angles: [0.; 3],
+            rotation: [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]],
+            quaternion: [0., 0., 0., 1.],
+            time_stamp: Duration::ZERO,
+            quality: MarkerQuality::default(),
+        }
+    }
+
+    #[test]
+    fn window_pads_all_markers() {
+        let setting = RoiSetting::default();
+        assert_eq!(window(&[], &setting), None);
+        let arucos = [
+            aruco([[100., 50.], [140., 50.], [140., 70.], [100., 70.]]),
+            aruco([[200., 60.], [220., 60.], [220., 80.], [200., 80.]]),
+        ];
+        // 120 pixels wide, padded by half of that
+        assert_eq!(window(&arucos, &setting), Some([40., -10., 280., 140.]));
+    }
+
+    #[test]
+    fn window_keeps_min_padding() {
+        let setting = RoiSetting::default();
+        let arucos = [aruco([
+            [100., 100.],
+            [110., 100.],
+            [110., 110.],
+            [100., 110.],
+        ])];
+        assert_eq!(window(&arucos, &setting), Some([76., 76., 134., 134.]));
+    }
+
+    #[test]
+    fn to_rect_scales_and_clips() {
+        assert_eq!(
+            to_rect([40., -10., 280., 140.], 2, 320, 240),
+            Some(Rect::new(20, 0, 120, 70))
+        );
+        assert_eq!(
+            to_rect([10.5, 20.5, 30.2, 40.9], 1, 320, 240),
+            Some(Rect::new(10, 20, 21, 21))
+        );
+        assert_eq!(
+            to_rect([300., 200., 400., 300.], 1, 320, 240),
+            Some(Rect::new(300, 200, 20, 40))
+        );
+        assert_eq!(to_rect([400., 300., 500., 400.], 1, 320, 240), None);
+    }
+}
@@ -1,23 +1,32 @@
-use burn::tensor::activation::relu;
+use std::{fs, path::Path};
+
 use burn_ndarray::{NdArray, NdArrayDevice};
-use nalgebra::Vector6;
+use nalgebra::{Quaternion, UnitQuaternion, Vector6};
+use opencv::{
+    calib3d::undistort_points_def,
+    core::{Mat, Point2f, Vector},
+};
 use serde::{Deserialize, Serialize};
 
 use crate::aruco_finder::Aruco;
 use crate::data_saver::FrameData;
+use crate::{errors::Error, CameraDistortion, CameraIntrinsic, RestPose, Result};
 
 use burn::record::Recorder;
 use burn::{
     module::Module,
     nn::{Linear, LinearConfig},
-    record::FullPrecisionSettings,
-    tensor::{backend::Backend, Tensor},
+    record::{FullPrecisionSettings, NamedMpkFileRecorder},
+    tensor::{activation, backend::Backend, Data, Shape, Tensor},
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FingerForceData {
     pub force: Option<Force>,
     pub time_stamp: std::time::Duration,
+    /// The marker was extrapolated by the tracker, not detected.
+    #[serde(default)]
+    pub predicted: bool,
 }
 
 impl FrameData for FingerForceData {
@@ -26,66 +35,302 @@ impl FrameData for FingerForceData {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Activation {
+    Relu,
+    Tanh,
+    Sigmoid,
+    Gelu,
+    /// No activation, for the output layer.
+    None,
+}
+
+impl Activation {
+    fn apply<B: Backend>(self, x: Tensor<B, 2>) -> Tensor<B, 2> {
+        match self {
+            Self::Relu => activation::relu(x),
+            Self::Tanh => activation::tanh(x),
+            Self::Sigmoid => activation::sigmoid(x),
+            Self::Gelu => activation::gelu(x),
+            Self::None => x,
+        }
+    }
+}
+
+/// One fully connected layer, `name` is its key in the checkpoint. The
+/// activation has to be given, the output layer usually wants `none`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayerConfig {
+    pub name: String,
+    pub width: usize,
+    pub activation: Activation,
+}
+
+/// Shape of the MLP the weights belong to. Dropout used in training has no
+/// weights and is a no-op at inference, so it is left out.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetConfig {
+    pub inputs: usize,
+    pub layers: Vec<LayerConfig>,
+}
+
+impl Default for NetConfig {
+    fn default() -> Self {
+        let layer = |name: &str, width, activation| LayerConfig {
+            name: name.into(),
+            width,
+            activation,
+        };
+        Self {
+            inputs: 8,
+            layers: vec![
+                layer("fc1", 150, Activation::Relu),
+                layer("fc2", 200, Activation::Relu),
+                layer("fc3", 200, Activation::Relu),
+                layer("fc4", 6, Activation::None),
+            ],
+        }
+    }
+}
+
+impl NetConfig {
+    pub fn outputs(&self) -> usize {
+        self.layers.last().map_or(self.inputs, |l| l.width)
+    }
+
+    // [inputs, outputs] of every layer, the order burn stores weights in
+    fn shapes(&self) -> Vec<[usize; 2]> {
+        let mut inputs = self.inputs;
+        self.layers
+            .iter()
+            .map(|l| [std::mem::replace(&mut inputs, l.width), l.width])
+            .collect()
+    }
+}
+
 #[derive(Module, Debug)]
 struct Net<B: Backend> {
-    fc1: Linear<B>,
-    fc2: Linear<B>,
-    fc3: Linear<B>,
-    fc4: Linear<B>,
+    layers: Vec<Linear<B>>,
 }
 
 impl<B: Backend> Net<B> {
-    pub fn init(device: &B::Device) -> Self {
-        let fc1 = LinearConfig::new(8, 150).init(device);
-        let fc2 = LinearConfig::new(150, 200).init(device);
-        let fc3 = LinearConfig::new(200, 200).init(device);
-        let fc4 = LinearConfig::new(200, 6).init(device);
-        Net { fc1, fc2, fc3, fc4 }
+    pub fn init(config: &NetConfig, device: &B::Device) -> Self {
+        let layers = config
+            .shapes()
+            .iter()
+            .map(|[i, o]| LinearConfig::new(*i, *o).init(device))
+            .collect();
+        Net { layers }
     }
 
-    pub fn forward(&self, x: Tensor<B, 2>) -> Tensor<B, 2> {
-        let x = relu(self.fc1.forward(x));
-        let x = relu(self.fc2.forward(x));
-        let x = relu(self.fc3.forward(x));
-        self.fc4.forward(x)
+    pub fn forward(&self, config: &NetConfig, x: Tensor<B, 2>) -> Tensor<B, 2> {
+        self.layers
+            .iter()
+            .zip(config.layers.iter())
+            .fold(x, |x, (layer, c)| c.activation.apply(layer.forward(x)))
     }
 }
 
-fn load_model(path: &str) -> Net<NdArray> {
-    type Backend = burn_ndarray::NdArray<f32>;
+#[cfg(feature = "pytorch")]
+fn load_model(path: &str, config: &NetConfig) -> Result<Net<NdArray>> {
     let device = Default::default();
-    let load_args = burn_import::pytorch::LoadArgs::new(path.into());
-        // .with_debug_print()
-        // .with_key_remap(r"model\.(\d)", "l$1");
+    let mut load_args = burn_import::pytorch::LoadArgs::new(path.into());
+    for (i, layer) in config.layers.iter().enumerate() {
+        load_args = load_args.with_key_remap(
+            &format!("^{}\\.(.*)", regex::escape(&layer.name)),
+            &format!("layers.{i}.$1"),
+        );
+    }
     let record = burn_import::pytorch::PyTorchFileRecorder::<FullPrecisionSettings>::default()
-        .load(load_args, &device)
-        .expect("Should decode state successfully");
-    Net::<Backend>::init(&device).load_record(record)
+        .load(load_args, &device)?;
+    build_model(path, config, record)
+}
+
+fn load_mpk(path: &str, config: &NetConfig) -> Result<Net<NdArray>> {
+    let device = Default::default();
+    let record =
+        NamedMpkFileRecorder::<FullPrecisionSettings>::default().load(path.into(), &device)?;
+    build_model(path, config, record)
+}
+
+fn build_model(path: &str, config: &NetConfig, record: NetRecord<NdArray>) -> Result<Net<NdArray>> {
+    if record.layers.len() != config.layers.len() {
+        return Err(Error::Config(format!(
+            "{path} has {} layers, the architecture {}",
+            record.layers.len(),
+            config.layers.len()
+        )));
+    }
+    for ((layer, expected), c) in record
+        .layers
+        .iter()
+        .zip(config.shapes())
+        .zip(config.layers.iter())
+    {
+        let found = layer.weight.val().dims();
+        if found != expected {
+            return Err(Error::Config(format!(
+                "layer {} in {path} is {}x{}, the architecture expects {}x{}",
+                c.name, found[0], found[1], expected[0], expected[1]
+            )));
+        }
+    }
+    Ok(Net::init(config, &Default::default()).load_record(record))
+}
+
+/// What the model is fed, taken from each detection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputFeatures {
+    /// The four corners in pixels.
+    #[default]
+    Corners,
+    /// The four corners undistorted, in normalized image coordinates, so
+    /// they do not depend on the camera.
+    NormalizedCorners,
+    /// Translation in meters and rotation vector in radians.
+    Pose,
+    /// `Pose` relative to the rest pose taken on tare.
+    PoseDelta,
+}
+
+impl InputFeatures {
+    /// Number of model inputs.
+    pub fn count(self) -> usize {
+        match self {
+            Self::Corners | Self::NormalizedCorners => 8,
+            Self::Pose | Self::PoseDelta => 6,
+        }
+    }
+}
+
+/// Normalization the model was trained with, stored next to the weights as
+/// `<model>.json`. Inputs are fed as `(x - input_mean) / input_std`, outputs
+/// are multiplied by `output_scale`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelMetadata {
+    pub input_mean: Vec<f32>,
+    pub input_std: Vec<f32>,
+    pub output_scale: Vec<f32>,
+    /// Units of the six outputs once scaled, e.g. `["N", "N", "N", "Nmm", "Nmm", "Nmm"]`.
+    #[serde(default)]
+    pub units: Vec<String>,
+    /// Camera `[width, height]` the corners were recorded at.
+    #[serde(default)]
+    pub resolution: Option<[u32; 2]>,
+    #[serde(default)]
+    pub features: InputFeatures,
+    #[serde(default)]
+    pub architecture: NetConfig,
+}
+
+impl Default for ModelMetadata {
+    fn default() -> Self {
+        Self {
+            input_mean: vec![0.; 8],
+            input_std: vec![1.; 8],
+            output_scale: vec![1.; 6],
+            units: vec![],
+            resolution: None,
+            features: InputFeatures::default(),
+            architecture: NetConfig::default(),
+        }
+    }
+}
+
+impl ModelMetadata {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let metadata: Self = serde_json::from_str(&fs::read_to_string(path)?)?;
+        metadata.validate()?;
+        Ok(metadata)
+    }
+
+    /// The sidecar of a model file, the default when there is none.
+    pub fn for_model(model_path: impl AsRef<Path>) -> Result<Self> {
+        let path = model_path.as_ref().with_extension("json");
+        if path.exists() {
+            Self::load(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let inputs = self.features.count();
+        if self.input_mean.len() != inputs || self.input_std.len() != inputs {
+            return Err(Error::Config(format!(
+                "input_mean and input_std need {inputs} values for {:?}, got {} and {}",
+                self.features,
+                self.input_mean.len(),
+                self.input_std.len()
+            )));
+        }
+        let net = &self.architecture;
+        if net.inputs != inputs {
+            return Err(Error::Config(format!(
+                "{:?} gives {inputs} inputs, the architecture takes {}",
+                self.features, net.inputs
+            )));
+        }
+        if net.outputs() != 6 {
+            return Err(Error::Config(format!(
+                "the model has to output a 6 axis force, the architecture gives {}",
+                net.outputs()
+            )));
+        }
+        if self.output_scale.len() != 6 {
+            return Err(Error::Config(format!(
+                "output_scale needs 6 values, got {}",
+                self.output_scale.len()
+            )));
+        }
+        if let Some(std) = self.input_std.iter().find(|s| !(s.is_finite() && **s > 0.)) {
+            return Err(Error::Config(format!(
+                "input_std must be positive, got {std}"
+            )));
+        }
+        Ok(())
+    }
 }
 
-// pub fn convert() {
-//     type Backend = burn_ndarray::NdArray<f32>;
-//     let device = Default::default();
+/// What feature extraction needs to know about one finger besides its
+/// detection, so one model can serve several fingers.
+#[derive(Default)]
+pub struct FingerContext {
+    // camera matrix and distortion for normalized corners
+    camera: Option<(Mat, Vector<f64>)>,
+    rest_pose: Option<RestPose>,
+}
 
-//     // Load PyTorch weights into a model record.
-//     let load_args = burn_import::pytorch::LoadArgs::new("./model.pth".into())
-//         // .with_debug_print()
-//         .with_key_remap(r"model\.(\d)", "l$1");
-//     let record: NetRecord<Backend> =
-//         burn_import::pytorch::PyTorchFileRecorder::<FullPrecisionSettings>::default()
-//             .load(load_args, &device)
-//             .expect("Failed to decode state");
+impl FingerContext {
+    /// Camera the detections come from, needed for normalized corners.
+    pub fn set_camera(
+        &mut self,
+        intrinsic: &CameraIntrinsic,
+        distortion: &CameraDistortion,
+    ) -> Result<()> {
+        self.camera = Some((
+            Mat::from_slice_2d(&intrinsic.camera_matrix())?,
+            Vector::from_slice(distortion.as_slice()),
+        ));
+        Ok(())
+    }
 
-//     // Save the model record to a file.
-//     let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::default();
+    /// Baseline for `InputFeatures::PoseDelta`, usually from a tare.
+    pub fn set_rest_pose(&mut self, rest_pose: Option<RestPose>) {
+        self.rest_pose = rest_pose;
+    }
 
-//     recorder
-//         .record(record, "./model".into())
-//         .expect("Failed to save model record");
-// }
+    pub fn rest_pose(&self) -> Option<&RestPose> {
+        self.rest_pose.as_ref()
+    }
+}
 
 pub struct SoftFinger {
     model: Net<NdArray>,
+    metadata: ModelMetadata,
+    context: FingerContext,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -94,30 +339,275 @@ pub struct Force {
 }
 
 impl SoftFinger {
+    /// Load the weights and the `<model>.json` metadata next to them.
+    #[cfg(feature = "pytorch")]
+    #[deprecated(note = "use `load_pt`, which returns errors instead of panicking")]
     pub fn new_pt(pt_path: &str) -> Self {
-        SoftFinger {
-            model: load_model(pt_path).no_grad(),
+        Self::load_pt(pt_path).unwrap()
+    }
+
+    #[cfg(feature = "pytorch")]
+    pub fn load_pt(pt_path: &str) -> Result<Self> {
+        let metadata = ModelMetadata::for_model(pt_path)?;
+        Self::new_pt_with_metadata(pt_path, metadata)
+    }
+
+    #[cfg(feature = "pytorch")]
+    pub fn new_pt_with_metadata(pt_path: &str, metadata: ModelMetadata) -> Result<Self> {
+        metadata.validate()?;
+        Ok(SoftFinger {
+            model: load_model(pt_path, &metadata.architecture)?.no_grad(),
+            metadata,
+            context: FingerContext::default(),
+        })
+    }
+
+    /// Load weights converted to burn's format by `convert-model`, with the
+    /// `<model>.json` metadata next to them.
+    pub fn new_mpk(mpk_path: &str) -> Result<Self> {
+        let metadata = ModelMetadata::for_model(mpk_path)?;
+        Ok(SoftFinger {
+            model: load_mpk(mpk_path, &metadata.architecture)?.no_grad(),
+            metadata,
+            context: FingerContext::default(),
+        })
+    }
+
+    /// `new_mpk` for `.mpk` files, `load_pt` otherwise.
+    pub fn load(path: &str) -> Result<Self> {
+        match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("mpk") => Self::new_mpk(path),
+            #[cfg(feature = "pytorch")]
+            _ => Self::load_pt(path),
+            #[cfg(not(feature = "pytorch"))]
+            _ => Err(Error::Config(format!(
+                "{path} is not a .mpk model and PyTorch support is not built in"
+            ))),
         }
     }
 
-    pub fn predict_force(&self, aruco: &Aruco) -> Force {
-        println!("{aruco:?}");
-        let x = self.model.forward(Tensor::<NdArray, 2>::from_data(
-            [[
-                aruco.corners[0][0],
-                aruco.corners[0][1],
-                aruco.corners[1][0],
-                aruco.corners[1][1],
-                aruco.corners[2][0],
-                aruco.corners[2][1],
-                aruco.corners[3][0],
-                aruco.corners[3][1],
-            ]],
-            &NdArrayDevice::default(),
-        ));
-        let data = x.to_data();
-        Force {
-            value: Vector6::from_vec(data.value),
+    /// Write the weights in burn's format and the metadata next to them.
+    pub fn save_mpk(&self, mpk_path: &str) -> Result<()> {
+        NamedMpkFileRecorder::<FullPrecisionSettings>::default()
+            .record(self.model.clone().into_record(), mpk_path.into())?;
+        let metadata = Path::new(mpk_path).with_extension("json");
+        fs::write(metadata, serde_json::to_string_pretty(&self.metadata)?)?;
+        Ok(())
+    }
+
+    pub fn metadata(&self) -> &ModelMetadata {
+        &self.metadata
+    }
+
+    /// Fails when the model was trained at another camera resolution.
+    pub fn check_resolution(&self, width: u32, height: u32) -> Result<()> {
+        match self.metadata.resolution {
+            Some([w, h]) if (w, h) != (width, height) => Err(Error::Config(format!(
+                "model expects {w}x{h} frames, camera gives {width}x{height}"
+            ))),
+            _ => Ok(()),
         }
     }
+
+    /// Camera of the finger `predict_force` is used for.
+    pub fn set_camera(
+        &mut self,
+        intrinsic: &CameraIntrinsic,
+        distortion: &CameraDistortion,
+    ) -> Result<()> {
+        self.context.set_camera(intrinsic, distortion)
+    }
+
+    /// Rest pose of the finger `predict_force` is used for.
+    pub fn set_rest_pose(&mut self, rest_pose: Option<RestPose>) {
+        self.context.set_rest_pose(rest_pose);
+    }
+
+    /// Model inputs for a detection, before normalization.
+    pub fn extract_features(&self, aruco: &Aruco) -> Result<Vec<f32>> {
+        self.extract_features_in(aruco, &self.context)
+    }
+
+    /// Like `extract_features` for a detection of another finger.
+    pub fn extract_features_in(&self, aruco: &Aruco, context: &FingerContext) -> Result<Vec<f32>> {
+        let features = match self.metadata.features {
+            InputFeatures::Corners => aruco.corners.iter().flatten().copied().collect(),
+            InputFeatures::NormalizedCorners => {
+                let Some((camera_matrix, dist_coeffs)) = &context.camera else {
+                    return Err(Error::Config(
+                        "normalized_corners features need the camera, see set_camera".into(),
+                    ));
+                };
+                let corners: Vector<Point2f> = aruco
+                    .corners
+                    .iter()
+                    .map(|c| Point2f::new(c[0], c[1]))
+                    .collect();
+                let mut normalized = Vector::<Point2f>::new();
+                undistort_points_def(&corners, &mut normalized, camera_matrix, dist_coeffs)?;
+                normalized.iter().flat_map(|p| [p.x, p.y]).collect()
+            }
+            InputFeatures::Pose => {
+                let q = aruco.quaternion;
+                let rotation =
+                    UnitQuaternion::from_quaternion(Quaternion::new(q[3], q[0], q[1], q[2]))
+                        .scaled_axis();
+                aruco
+                    .trans
+                    .iter()
+                    .chain(rotation.iter())
+                    .map(|v| *v as f32)
+                    .collect()
+            }
+            InputFeatures::PoseDelta => {
+                let Some(rest_pose) = &context.rest_pose else {
+                    return Err(Error::Config(
+                        "pose_delta features need a rest pose, tare the finger first".into(),
+                    ));
+                };
+                let Some(delta) = rest_pose.displacement(aruco) else {
+                    return Err(Error::Config(format!(
+                        "rest pose is for marker {}, not {}",
+                        rest_pose.id, aruco.id
+                    )));
+                };
+                delta
+                    .trans
+                    .iter()
+                    .chain(delta.rotation.iter())
+                    .map(|v| *v as f32)
+                    .collect()
+            }
+        };
+        Ok(features)
+    }
+
+    pub fn predict_force(&self, aruco: &Aruco) -> Result<Force> {
+        let features = self.extract_features(aruco)?;
+        self.predict_features(&features)
+    }
+
+    /// Forces for detections of several fingers or markers in one forward
+    /// pass, in the order of `items`.
+    pub fn predict_batch(&self, items: &[(&Aruco, &FingerContext)]) -> Vec<Result<Force>> {
+        let features: Vec<Result<Vec<f32>>> = items
+            .iter()
+            .map(|(aruco, context)| {
+                let features = self.extract_features_in(aruco, context)?;
+                self.check_inputs(&features)?;
+                Ok(features)
+            })
+            .collect();
+        let rows: Vec<&[f32]> = features
+            .iter()
+            .filter_map(|f| f.as_ref().ok().map(|f| f.as_slice()))
+            .collect();
+        let mut forces = self.forward(&rows).into_iter();
+        features
+            .into_iter()
+            .map(|f| f.map(|_| forces.next().unwrap()))
+            .collect()
+    }
+
+    /// Force for raw, not yet normalized, model inputs.
+    pub fn predict_features(&self, features: &[f32]) -> Result<Force> {
+        self.check_inputs(features)?;
+        Ok(self.forward(&[features]).remove(0))
+    }
+
+    fn check_inputs(&self, features: &[f32]) -> Result<()> {
+        let inputs = self.metadata.input_mean.len();
+        if features.len() != inputs {
+            return Err(Error::Config(format!(
+                "the model takes {inputs} inputs, got {}",
+                features.len()
+            )));
+        }
+        Ok(())
+    }
+
+    // one forward pass over raw inputs, a row per force, each row as long as
+    // `input_mean`
+    fn forward(&self, rows: &[&[f32]]) -> Vec<Force> {
+        if rows.is_empty() {
+            return vec![];
+        }
+        let m = &self.metadata;
+        let input: Vec<f32> = rows
+            .iter()
+            .flat_map(|row| {
+                row.iter()
+                    .zip(m.input_mean.iter().zip(m.input_std.iter()))
+                    .map(|(x, (mean, std))| (x - mean) / std)
+            })
+            .collect();
+        let shape = Shape::new([rows.len(), m.input_mean.len()]);
+        let x = self.model.forward(
+            &m.architecture,
+            Tensor::<NdArray, 2>::from_data(Data::new(input, shape), &NdArrayDevice::default()),
+        );
+        let scale = Vector6::from_column_slice(&m.output_scale);
+        x.to_data()
+            .value
+            .chunks(6)
+            .map(|row| Force {
+                value: Vector6::from_column_slice(row).component_mul(&scale),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shapes_chain_layer_widths() {
+        assert_eq!(
+            NetConfig::default().shapes(),
+            vec![[8, 150], [150, 200], [200, 200], [200, 6]]
+        );
+        let empty = NetConfig {
+            inputs: 8,
+            layers: vec![],
+        };
+        assert!(empty.shapes().is_empty());
+        assert_eq!(empty.outputs(), 8);
+    }
+
+    #[test]
+    fn validate_default_metadata() {
+        ModelMetadata::default().validate().unwrap();
+    }
+
+    #[test]
+    fn validate_input_count() {
+        let mut metadata = ModelMetadata {
+            features: InputFeatures::Pose,
+            ..Default::default()
+        };
+        // 8 normalization values for 6 pose inputs
+        assert!(metadata.validate().is_err());
+        metadata.input_mean = vec![0.; 6];
+        metadata.input_std = vec![1.; 6];
+        // the architecture still takes 8
+        assert!(metadata.validate().is_err());
+        metadata.architecture.inputs = 6;
+        metadata.validate().unwrap();
+    }
+
+    #[test]
+    fn validate_outputs_and_std() {
+        let mut metadata = ModelMetadata::default();
+        metadata.output_scale.pop();
+        assert!(metadata.validate().is_err());
+
+        let mut metadata = ModelMetadata::default();
+        metadata.architecture.layers.last_mut().unwrap().width = 3;
+        assert!(metadata.validate().is_err());
+
+        let mut metadata = ModelMetadata::default();
+        metadata.input_std[2] = 0.;
+        assert!(metadata.validate().is_err());
+    }
 }
@@ -1,6 +1,24 @@
-use std::{io, time::Duration};
+use std::{
+    collections::VecDeque,
+    io,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
 
-use crate::Result;
+use crate::{
+    camera_control::{
+        value_as_i64, CameraControl, ControlInfo, ControlProfile, EXPOSURE_APERTURE_PRIORITY,
+        EXPOSURE_MANUAL,
+    },
+    camera_identity::CameraIdentity,
+    camera_mode::{choose_mode, enum_modes, fraction_fps, CameraMode, NegotiationPolicy},
+    errors::Error,
+    frame_source::encode_rgb,
+    pixel_format::{grey_to_rgb, nv12_to_rgb, yuyv_to_rgb, GrayFrame, PixelFormat},
+    CameraEvent, FrameSource, FrameStats, Result,
+};
+use opencv::core::Mat;
 // use opencv::{
 //     core::{Mat, CV_8UC3},
 //     imgproc::{cvt_color_def, COLOR_RGB2BGR},
@@ -9,30 +27,70 @@ use crate::Result;
 use std::fmt::Debug;
 // use tokio::sync::mpsc;
 use v4l::{
-    buffer::Metadata,
-    frameinterval::FrameIntervalEnum,
-    framesize::FrameSizeEnum,
+    control::{Control, Value},
     io::traits::CaptureStream,
     prelude::MmapStream,
     video::{capture::Parameters, Capture},
     Device, Format, FourCC, Fraction,
 };
 
+// first retry after a disconnect, doubled on every failed attempt
+const RECONNECT_BACKOFF_MIN: Duration = Duration::from_millis(100);
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(5);
+// a stream that delivers nothing for this long is treated as gone
+const STREAM_TIMEOUT: Duration = Duration::from_secs(2);
+
+// how to find the device again after it went away
+#[derive(Debug, Clone)]
+enum CameraTarget {
+    Index(usize),
+    Path(String),
+    Identity(CameraIdentity),
+}
+
+impl CameraTarget {
+    fn open(&self) -> Result<Device> {
+        let device = match self {
+            CameraTarget::Index(index) => Device::new(*index)?,
+            CameraTarget::Path(path) => Device::with_path(path)?,
+            CameraTarget::Identity(identity) => Device::with_path(identity.find()?.path)?,
+        };
+        Ok(device)
+    }
+}
+
+struct Reconnect {
+    lost_at: Instant,
+    attempts: u32,
+    backoff: Duration,
+    next_attempt: Instant,
+}
+
 pub struct Camera<'a> {
     device: Device,
+    target: CameraTarget,
+    profile: Option<ControlProfile>,
+    // Some while the device is gone
+    reconnect: Option<Reconnect>,
+    events: VecDeque<CameraEvent>,
+    stats: FrameStats,
     stream: Option<MmapStream<'a>>,
-    fps: u32,
+    // what was actually set up, reopened as is after a disconnect
+    mode: CameraMode,
     width: u32,
     height: u32,
     format: FourCC,
+    pixel_format: PixelFormat,
+    stride: u32,
     // index: usize,
     rgb_buffer: Vec<u8>,
+    gray: GrayFrame,
 }
 
 impl Debug for Camera<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Camera")
-            .field("fps", &self.fps)
+            .field("fps", &self.mode.fps)
             .field("width", &self.width)
             .field("height", &self.height)
             .field("format", &self.format.to_string())
@@ -42,112 +100,484 @@ impl Debug for Camera<'_> {
 
 impl Camera<'_> {
     pub fn new_with_path(path: &str, width: u32, height: u32, fps: u32) -> Result<Self> {
-        let dev = Device::with_path(path).unwrap_or_else(|_| panic!("can not found camera:{path}"));
-        Self::new_from_device(dev, width, height, fps)
+        Self::new_with_path_and_policy(path, width, height, fps, NegotiationPolicy::Exact)
+    }
+
+    /// Like `new_with_path`, but fall back to the closest mode the device
+    /// offers according to `policy`.
+    pub fn new_with_path_and_policy(
+        path: &str,
+        width: u32,
+        height: u32,
+        fps: u32,
+        policy: NegotiationPolicy,
+    ) -> Result<Self> {
+        let target = CameraTarget::Path(path.to_string());
+        Self::new_from_target(target, width, height, fps, policy)
+    }
+
+    /// Open the camera matching `identity`. After a disconnect the camera is
+    /// looked up again, so it may come back under another `/dev/video*`.
+    pub fn new_with_identity(
+        identity: &CameraIdentity,
+        width: u32,
+        height: u32,
+        fps: u32,
+        policy: NegotiationPolicy,
+    ) -> Result<Self> {
+        let target = CameraTarget::Identity(identity.clone());
+        Self::new_from_target(target, width, height, fps, policy)
     }
 
     pub fn new(index: usize, width: u32, height: u32, fps: u32) -> Result<Self> {
-        let dev = Device::new(index).unwrap_or_else(|_| panic!("can not found camera{index}"));
-        Self::new_from_device(dev, width, height, fps)
+        let target = CameraTarget::Index(index);
+        Self::new_from_target(target, width, height, fps, NegotiationPolicy::Exact)
     }
 
-    fn new_from_device(device: Device, width: u32, height: u32, fps: u32) -> Result<Self> {
+    fn new_from_target(
+        target: CameraTarget,
+        width: u32,
+        height: u32,
+        fps: u32,
+        policy: NegotiationPolicy,
+    ) -> Result<Self> {
+        let device = target.open()?;
         // let device = Device::new(index).unwrap_or_else(|_| panic!("can not found camera{index}"));
-        let mut choosed_format = None;
-        for format in device
-            .enum_formats()
-            .unwrap_or_else(|_| panic!("enum formats fail (camera)"))
-        {
-            for frame_size in device
-                .enum_framesizes(format.fourcc)
-                .unwrap_or_else(|_| panic!("enum framesizes fail (camera)"))
-            {
-                if let FrameSizeEnum::Discrete(size) = frame_size.size {
-                    for fi in device
-                        .enum_frameintervals(format.fourcc, size.width, size.height)
-                        .unwrap_or_else(|_| panic!("enum frameintervals fail (camera)"))
-                    {
-                        if let FrameIntervalEnum::Discrete(fraction) = fi.interval {
-                            // println!("{size} {fraction}");
-                            if size.width == width
-                                && size.height == height
-                                && fraction.denominator == fps
-                            {
-                                choosed_format = Some((width, height, format.fourcc));
-                            }
-                        }
-                    }
-                }
-            }
-        }
-
-        // let caps = device.query_controls().unwrap();
-        // device.set_control(Control{
-        //     id: 0x00980900,
-        //     value: v4l::control::Value::Integer(64)
-        // }).unwrap();
-        // println!("{caps}");
-        if choosed_format.is_none() {
+        let modes = enum_modes(&device, width, height, fps)?;
+        let Some(mode) = choose_mode(&modes, width, height, fps, policy) else {
             // 可能是参数设置不好，don’t panic
-            return Err(io::Error::other("no camera availbale"))?;
-        }
+            return Err(Error::NoCameraMode {
+                requested: format!("{width}x{height}@{fps}"),
+                available: modes,
+            });
+        };
+        Self::new_with_mode(device, target, mode)
+    }
 
-        let (width, height, fourcc) = choosed_format.unwrap();
-        let real_format = device
-            .set_format(&Format::new(width, height, fourcc))
-            .unwrap_or_else(|_| panic!("set format fail (camera)"));
-        let real_params = device
-            .set_params(&Parameters::new(Fraction::new(1, fps)))
-            .unwrap_or_else(|_| panic!("set params fail (camera)"));
+    fn new_with_mode(device: Device, target: CameraTarget, mode: CameraMode) -> Result<Self> {
+        let (width, height, fourcc, fps) = (mode.width, mode.height, mode.fourcc, mode.fps);
+        let real_format = device.set_format(&Format::new(width, height, fourcc))?;
+        let real_params = device.set_params(&Parameters::new(Fraction::new(1, fps)))?;
+        // the driver may hand back something else than asked for
+        let pixel_format = PixelFormat::from_fourcc(real_format.fourcc).ok_or_else(|| {
+            io::Error::other(format!("unsupported pixel format {}", real_format.fourcc))
+        })?;
         let mut cam = Camera {
             stream: None,
             device,
+            target,
+            profile: None,
+            reconnect: None,
+            events: VecDeque::new(),
+            stats: FrameStats::default(),
             // index,
-            fps: real_params.interval.denominator,
+            mode: CameraMode {
+                fourcc: real_format.fourcc,
+                width: real_format.width,
+                height: real_format.height,
+                fps: fraction_fps(real_params.interval),
+            },
             format: real_format.fourcc,
+            pixel_format,
+            stride: real_format
+                .stride
+                .max(pixel_format.min_stride(real_format.width)),
             width: real_format.width,
             height: real_format.height,
             rgb_buffer: vec![0u8; (real_format.height * real_format.width * 3) as usize],
+            gray: GrayFrame::new(1),
         };
-        cam.open();
+        cam.open()?;
         Ok(cam)
     }
 
-    fn open(&mut self) {
-        let stream = MmapStream::new(&self.device, v4l::buffer::Type::VideoCapture)
-            .unwrap_or_else(|_| panic!("new mmap stream fail (camera)"));
+    fn open(&mut self) -> Result<()> {
+        let mut stream = MmapStream::new(&self.device, v4l::buffer::Type::VideoCapture)?;
+        stream.set_timeout(STREAM_TIMEOUT);
         // stream.start()?;
         self.stream = Some(stream);
+        Ok(())
+    }
+
+    /// Disconnect and reconnect notifications since the last call.
+    pub fn take_events(&mut self) -> Vec<CameraEvent> {
+        self.events.drain(..).collect()
+    }
+
+    /// Delivered, dropped and undecodable frame counts since the camera was
+    /// opened, plus the sequence number of the latest frame.
+    pub fn stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.reconnect.is_none()
     }
 
+    // unplugging shows up as ENODEV or EIO, a stalled hub as a timeout
+    fn is_disconnect(&self, e: &io::Error) -> bool {
+        const EIO: i32 = 5;
+        const ENXIO: i32 = 6;
+        const ENODEV: i32 = 19;
+        if let CameraTarget::Path(path) = &self.target {
+            if !Path::new(path).exists() {
+                return true;
+            }
+        }
+        e.kind() == io::ErrorKind::TimedOut
+            || matches!(e.raw_os_error(), Some(EIO | ENXIO | ENODEV))
+    }
+
+    fn on_stream_error(&mut self, e: io::Error) -> Error {
+        if !self.is_disconnect(&e) {
+            return e.into();
+        }
+        let now = Instant::now();
+        self.stream = None;
+        self.reconnect = Some(Reconnect {
+            lost_at: now,
+            attempts: 0,
+            backoff: RECONNECT_BACKOFF_MIN,
+            next_attempt: now + RECONNECT_BACKOFF_MIN,
+        });
+        self.events.push_back(CameraEvent::Disconnected {
+            reason: e.to_string(),
+        });
+        Error::Disconnected
+    }
+
+    // Blocks until the next reconnect attempt is due, then tries once.
+    fn ensure_connected(&mut self) -> Result<()> {
+        let Some(reconnect) = self.reconnect.as_mut() else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        if reconnect.next_attempt > now {
+            thread::sleep(reconnect.next_attempt - now);
+        }
+        reconnect.attempts += 1;
+
+        // same mode as before, no negotiation
+        let fresh = self
+            .target
+            .open()
+            .and_then(|device| Camera::new_with_mode(device, self.target.clone(), self.mode));
+        let reconnect = self.reconnect.as_mut().unwrap();
+        match fresh {
+            Ok(fresh) => {
+                let (attempts, downtime) = (reconnect.attempts, reconnect.lost_at.elapsed());
+                self.device = fresh.device;
+                self.stream = fresh.stream;
+                self.mode = fresh.mode;
+                self.width = fresh.width;
+                self.height = fresh.height;
+                self.format = fresh.format;
+                self.pixel_format = fresh.pixel_format;
+                self.stride = fresh.stride;
+                self.rgb_buffer = fresh.rgb_buffer;
+                self.reconnect = None;
+                // the driver counts from zero again
+                self.stats.sequence = None;
+                self.events
+                    .push_back(CameraEvent::Reconnected { attempts, downtime });
+                // controls reset with the device, frames still come without them
+                if let Some(profile) = self.profile.clone() {
+                    if let Err(e) = self.apply_profile(&profile) {
+                        self.events.push_back(CameraEvent::ProfileFailed {
+                            reason: format!("{e:?}"),
+                        });
+                    }
+                }
+                Ok(())
+            }
+            Err(e) => {
+                reconnect.backoff = (reconnect.backoff * 2).min(RECONNECT_BACKOFF_MAX);
+                reconnect.next_attempt = Instant::now() + reconnect.backoff;
+                self.events.push_back(CameraEvent::ReconnectFailed {
+                    attempt: reconnect.attempts,
+                    retry_in: reconnect.backoff,
+                    reason: format!("{e:?}"),
+                });
+                Err(Error::Disconnected)
+            }
+        }
+    }
+
+    /// Every control the device exposes, with its current value.
+    pub fn controls(&self) -> Result<Vec<ControlInfo>> {
+        let mut infos = vec![];
+        for desc in self.device.query_controls()? {
+            if desc.typ == v4l::control::Type::CtrlClass {
+                continue;
+            }
+            let value = self
+                .device
+                .control(desc.id)
+                .ok()
+                .and_then(|c| value_as_i64(&c.value));
+            infos.push(ControlInfo::new(&desc, value));
+        }
+        Ok(infos)
+    }
+
+    pub fn control(&self, control: CameraControl) -> Result<i64> {
+        let c = self.device.control(control.id())?;
+        value_as_i64(&c.value).ok_or_else(|| {
+            io::Error::other(format!("control {control:?} is not an integer")).into()
+        })
+    }
+
+    pub fn set_control(&self, control: CameraControl, value: i64) -> Result<()> {
+        let id = control.id();
+        // boolean controls want a boolean back
+        let value = match self.device.control(id)?.value {
+            Value::Boolean(_) => Value::Boolean(value != 0),
+            _ => Value::Integer(value),
+        };
+        self.device.set_control(Control { id, value })?;
+        Ok(())
+    }
+
+    /// Set every control of the profile, auto toggles first. The profile is
+    /// applied again after a reconnect.
+    pub fn apply_profile(&mut self, profile: &ControlProfile) -> Result<()> {
+        for (control, value) in &profile.values {
+            self.set_control(*control, *value)?;
+        }
+        self.profile = Some(profile.clone());
+        Ok(())
+    }
+
+    /// Exposure time in 100 µs units.
+    pub fn exposure(&self) -> Result<i64> {
+        self.control(CameraControl::ExposureAbsolute)
+    }
+
+    /// Switch to manual exposure and lock the exposure time (100 µs units).
+    pub fn set_exposure(&self, value: i64) -> Result<()> {
+        self.set_control(CameraControl::AutoExposure, EXPOSURE_MANUAL)?;
+        self.set_control(CameraControl::ExposureAbsolute, value)
+    }
+
+    pub fn set_auto_exposure(&self, auto: bool) -> Result<()> {
+        let mode = if auto {
+            EXPOSURE_APERTURE_PRIORITY
+        } else {
+            EXPOSURE_MANUAL
+        };
+        self.set_control(CameraControl::AutoExposure, mode)
+    }
+
+    pub fn gain(&self) -> Result<i64> {
+        self.control(CameraControl::Gain)
+    }
+
+    pub fn set_gain(&self, value: i64) -> Result<()> {
+        self.set_control(CameraControl::Gain, value)
+    }
+
+    /// White balance temperature in kelvin.
+    pub fn white_balance(&self) -> Result<i64> {
+        self.control(CameraControl::WhiteBalanceTemperature)
+    }
+
+    /// Switch off auto white balance and lock the temperature (kelvin).
+    pub fn set_white_balance(&self, kelvin: i64) -> Result<()> {
+        self.set_control(CameraControl::AutoWhiteBalance, 0)?;
+        self.set_control(CameraControl::WhiteBalanceTemperature, kelvin)
+    }
+
+    pub fn set_auto_white_balance(&self, auto: bool) -> Result<()> {
+        self.set_control(CameraControl::AutoWhiteBalance, auto as i64)
+    }
+
+    pub fn focus(&self) -> Result<i64> {
+        self.control(CameraControl::FocusAbsolute)
+    }
+
+    /// Switch off auto focus and lock the focus position.
+    pub fn set_focus(&self, value: i64) -> Result<()> {
+        self.set_control(CameraControl::AutoFocus, 0)?;
+        self.set_control(CameraControl::FocusAbsolute, value)
+    }
+
+    pub fn set_auto_focus(&self, auto: bool) -> Result<()> {
+        self.set_control(CameraControl::AutoFocus, auto as i64)
+    }
+
+    /// Every format, frame size and frame rate the device offers, including
+    /// formats `Camera` cannot decode.
+    pub fn supported_modes(&self) -> Result<Vec<CameraMode>> {
+        enum_modes(&self.device, self.width, self.height, self.mode.fps)
+    }
+
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.pixel_format
+    }
+
+    /// Shrink frames from `capture_gray` by `scale` in both directions.
+    pub fn set_gray_scale(&mut self, scale: u32) {
+        self.gray = GrayFrame::new(scale);
+    }
+
+    /// Size of the frames `capture_gray` hands out.
+    pub fn gray_size(&self) -> (u32, u32) {
+        let scale = self.gray.scale();
+        (self.width / scale, self.height / scale)
+    }
+
+    /// Next frame as JPEG. MJPEG cameras hand the buffer through untouched,
+    /// uncompressed formats are converted and encoded.
     pub fn capture_mjpeg(&mut self) -> Result<(Vec<u8>, Duration)> {
-        assert!(self.stream.is_some());
+        self.ensure_connected()?;
+        if self.pixel_format != PixelFormat::Mjpeg {
+            let (width, height) = (self.width, self.height);
+            let (rgb, time_stamp) = self.capture()?;
+            let data = encode_rgb(rgb, width, height, &mut Mat::default())?;
+            return Ok((data, time_stamp));
+        }
 
         // let start = std::time::Instant::now();
-        let (raw_mjpeg, Metadata { timestamp, .. }) = self.stream.as_mut().unwrap().next()?;
+        let (raw_mjpeg, meta) = match self.stream.as_mut().unwrap().next() {
+            Ok((raw, meta)) => (raw, *meta),
+            Err(e) => return Err(self.on_stream_error(e)),
+        };
         // println!("      stream next {:?}", start.elapsed());
+        self.stats.observe(meta.sequence);
+        self.stats.delivered += 1;
 
-        Ok((
-            raw_mjpeg.to_vec(),
-            Duration::new(timestamp.sec as u64, (timestamp.usec * 1000) as u32),
-        ))
+        Ok((raw_mjpeg.to_vec(), Duration::from(meta.timestamp)))
     }
 
+    /// Next frame as packed RGB, `width * height * 3` bytes.
     pub fn capture(&mut self) -> Result<(&[u8], Duration)> {
         use zune_jpeg::JpegDecoder;
 
+        let (w, h, stride) = (
+            self.width as usize,
+            self.height as usize,
+            self.stride as usize,
+        );
+        self.ensure_connected()?;
         // let start = std::time::Instant::now();
-        let (raw_mjpeg, Metadata { timestamp, .. }) = self.stream.as_mut().unwrap().next()?;
+        let (raw, meta) = match self.stream.as_mut().unwrap().next() {
+            Ok((raw, meta)) => (raw, *meta),
+            Err(e) => return Err(self.on_stream_error(e)),
+        };
+        self.stats.observe(meta.sequence);
         // println!("      stream next {:?}", start.elapsed());
 
         // let start = std::time::Instant::now();
-        let mut decoder = JpegDecoder::new(raw_mjpeg);
-        decoder.decode_into(&mut self.rgb_buffer)?; // shouldn't happend
-                                                    // println!("      decode {:?}", start.elapsed());
-
-        Ok((
-            &self.rgb_buffer,
-            Duration::new(timestamp.sec as u64, (timestamp.usec * 1000) as u32),
-        ))
+        match self.pixel_format {
+            PixelFormat::Mjpeg => {
+                let mut decoder = JpegDecoder::new(raw);
+                // shouldn't happend
+                if let Err(e) = decoder.decode_into(&mut self.rgb_buffer) {
+                    self.stats.decode_failed += 1;
+                    return Err(e.into());
+                }
+            }
+            PixelFormat::Yuyv => yuyv_to_rgb(raw, w, h, stride, &mut self.rgb_buffer),
+            PixelFormat::Nv12 => nv12_to_rgb(raw, w, h, stride, &mut self.rgb_buffer),
+            PixelFormat::Grey => grey_to_rgb(raw, w, h, stride, &mut self.rgb_buffer),
+        }
+        // println!("      decode {:?}", start.elapsed());
+        self.stats.delivered += 1;
+
+        Ok((&self.rgb_buffer, Duration::from(meta.timestamp)))
+    }
+
+    /// Next frame as 8 bit luminance, shrunk by the factor given to
+    /// `set_gray_scale`. Skips color conversion entirely, MJPEG frames only
+    /// have their luma decoded.
+    pub fn capture_gray(&mut self) -> Result<(&[u8], Duration)> {
+        let (w, h, stride) = (
+            self.width as usize,
+            self.height as usize,
+            self.stride as usize,
+        );
+        self.ensure_connected()?;
+        let (raw, meta) = match self.stream.as_mut().unwrap().next() {
+            Ok((raw, meta)) => (raw, *meta),
+            Err(e) => return Err(self.on_stream_error(e)),
+        };
+        self.stats.observe(meta.sequence);
+        let gray = match self.gray.convert(self.pixel_format, raw, w, h, stride) {
+            Ok(gray) => gray,
+            Err(e) => {
+                self.stats.decode_failed += 1;
+                return Err(e);
+            }
+        };
+        self.stats.delivered += 1;
+
+        Ok((gray, Duration::from(meta.timestamp)))
+    }
+
+    /// Next frame as JPEG with its luminance put into `gray`, straight from
+    /// the raw frame. Uncompressed frames are only converted to RGB for the
+    /// JPEG. Decode failures are left to the caller to count.
+    pub fn capture_mjpeg_gray(&mut self, gray: &mut GrayFrame) -> Result<(Vec<u8>, Duration)> {
+        let (w, h, stride) = (
+            self.width as usize,
+            self.height as usize,
+            self.stride as usize,
+        );
+        self.ensure_connected()?;
+        let (raw, meta) = match self.stream.as_mut().unwrap().next() {
+            Ok((raw, meta)) => (raw, *meta),
+            Err(e) => return Err(self.on_stream_error(e)),
+        };
+        self.stats.observe(meta.sequence);
+        gray.convert(self.pixel_format, raw, w, h, stride)?;
+        let time_stamp = Duration::from(meta.timestamp);
+        match self.pixel_format {
+            PixelFormat::Mjpeg => {
+                self.stats.delivered += 1;
+                return Ok((raw.to_vec(), time_stamp));
+            }
+            PixelFormat::Yuyv => yuyv_to_rgb(raw, w, h, stride, &mut self.rgb_buffer),
+            PixelFormat::Nv12 => nv12_to_rgb(raw, w, h, stride, &mut self.rgb_buffer),
+            PixelFormat::Grey => grey_to_rgb(raw, w, h, stride, &mut self.rgb_buffer),
+        }
+        self.stats.delivered += 1;
+        let (width, height) = (self.width, self.height);
+        let jpeg = encode_rgb(&self.rgb_buffer, width, height, &mut Mat::default())?;
+
+        Ok((jpeg, time_stamp))
+    }
+}
+
+impl FrameSource for Camera<'_> {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn fps(&self) -> u32 {
+        self.mode.fps
+    }
+
+    fn capture_mjpeg(&mut self) -> Result<(Vec<u8>, Duration)> {
+        Camera::capture_mjpeg(self)
+    }
+
+    fn capture(&mut self) -> Result<(&[u8], Duration)> {
+        Camera::capture(self)
+    }
+
+    fn capture_mjpeg_gray(&mut self, gray: &mut GrayFrame) -> Result<(Vec<u8>, Duration)> {
+        Camera::capture_mjpeg_gray(self, gray)
+    }
+
+    fn take_events(&mut self) -> Vec<CameraEvent> {
+        Camera::take_events(self)
+    }
+
+    fn stats(&self) -> FrameStats {
+        Camera::stats(self)
     }
 }
@@ -0,0 +1,155 @@
+use std::time::Duration;
+
+use clap::{Parser, ValueEnum};
+use opencv::core::{Mat, CV_8UC1};
+use rpi::{
+    open_replay, ArucoDictionary, CalibrationPattern, Calibrator, Camera, CameraMap, CameraRole,
+    Error, FrameSource, GrayFrame, NegotiationPolicy,
+};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Pattern {
+    Chessboard,
+    Charuco,
+}
+
+/// Collect views of a chessboard or ChArUco board and write the camera
+/// calibration as JSON.
+#[derive(Parser, Debug)]
+struct Args {
+    /// camera device, e.g. /dev/video0
+    #[arg(long)]
+    path: Option<String>,
+
+    /// json file mapping camera roles to identities, used with `--role`
+    #[arg(long)]
+    cameras: Option<String>,
+
+    #[arg(long, value_enum)]
+    role: Option<CameraRole>,
+
+    /// calibrate from a recording instead of a live camera
+    #[arg(long)]
+    replay: Option<String>,
+
+    #[arg(long, default_value_t = 640)]
+    width: u32,
+
+    #[arg(long, default_value_t = 480)]
+    height: u32,
+
+    #[arg(long, default_value_t = 30)]
+    fps: u32,
+
+    #[arg(long, value_enum, default_value_t = Pattern::Chessboard)]
+    pattern: Pattern,
+
+    /// inner corners for a chessboard, squares for ChArUco
+    #[arg(long, default_value_t = 9)]
+    cols: u32,
+
+    #[arg(long, default_value_t = 6)]
+    rows: u32,
+
+    /// square side in meters
+    #[arg(long, default_value_t = 0.025)]
+    square: f32,
+
+    /// ChArUco marker side in meters
+    #[arg(long, default_value_t = 0.018)]
+    marker: f32,
+
+    /// ChArUco marker dictionary, e.g. DICT_5X5_100
+    #[arg(long, default_value = "DICT_4X4_50")]
+    dictionary: String,
+
+    /// views to collect before calibrating
+    #[arg(long, default_value_t = 25)]
+    views: usize,
+
+    /// minimum time between two accepted views, so the board gets moved
+    #[arg(long, default_value_t = 500)]
+    interval_ms: u64,
+
+    #[arg(long, default_value = "calibration.json")]
+    output: String,
+}
+
+fn main() -> Result<(), Error> {
+    let args = Args::parse();
+    let mut camera: Box<dyn FrameSource> = if let Some(replay) = &args.replay {
+        open_replay(replay, args.fps)?
+    } else {
+        let path = match (&args.path, &args.cameras, args.role) {
+            (Some(path), _, _) => path.clone(),
+            (None, Some(cameras), Some(role)) => CameraMap::load(cameras)?
+                .find(role)?
+                .to_string_lossy()
+                .to_string(),
+            _ => return Err(Error::Config("give --path or --cameras with --role".into())),
+        };
+        Box::new(Camera::new_with_path_and_policy(
+            &path,
+            args.width,
+            args.height,
+            args.fps,
+            NegotiationPolicy::PreferResolution,
+        )?)
+    };
+    let (width, height) = (camera.width(), camera.height());
+    println!("calibrating at {width}x{height}");
+
+    let pattern = match args.pattern {
+        Pattern::Chessboard => CalibrationPattern::Chessboard {
+            cols: args.cols,
+            rows: args.rows,
+            square: args.square,
+        },
+        Pattern::Charuco => CalibrationPattern::Charuco {
+            cols: args.cols,
+            rows: args.rows,
+            square: args.square,
+            marker: args.marker,
+            dictionary: args.dictionary.parse::<ArucoDictionary>()?,
+        },
+    };
+    let mut calibrator = Calibrator::new(pattern)?;
+    let mut gray = GrayFrame::new(1);
+    let interval = Duration::from_millis(args.interval_ms);
+    let mut last_view: Option<Duration> = None;
+    while calibrator.views() < args.views {
+        let (rgb, time_stamp) = match camera.capture() {
+            Ok(frame) => frame,
+            Err(Error::EndOfStream) => break,
+            Err(_e) => continue,
+        };
+        if last_view.is_some_and(|t| time_stamp.saturating_sub(t) < interval) {
+            continue;
+        }
+        let gray_data = gray.convert_rgb(rgb, width, height).as_ptr();
+        let gray_img = unsafe {
+            Mat::new_rows_cols_with_data_unsafe_def(
+                height as i32,
+                width as i32,
+                CV_8UC1,
+                gray_data as *mut _,
+            )
+        }?;
+        if calibrator.add_view(&gray_img)? {
+            last_view = Some(time_stamp);
+            println!("view {}/{}", calibrator.views(), args.views);
+        }
+    }
+
+    let calibration = calibrator.calibrate()?;
+    println!(
+        "reprojection error {:.4} px over {} views",
+        calibration.reprojection_error,
+        calibrator.views()
+    );
+    println!("{:?}", calibration.camera_intrinsic);
+    println!("{:?}", calibration.camera_distortion);
+    calibration.save(&args.output)?;
+    println!("written to {}", args.output);
+    Ok(())
+}
@@ -1,15 +1,25 @@
+use std::{thread, time::Duration};
+
 use clap::Parser;
 use opencv::{
     core::{Mat, Vector, VectorToVec, CV_8UC3},
     imgcodecs::imencode_def,
     imgproc::{cvt_color_def, COLOR_RGB2BGR},
 };
-use rpi::Camera;
+use rpi::{
+    list_cameras, open_replay, ArucoFinder, ArucoFinderSetting, Camera, CameraMap, CameraRole,
+    ControlProfile, Error, FrameInfo, FrameSource, NegotiationPolicy, SyntheticSource,
+};
 use zenoh::prelude::sync::*;
 
+// first pause after a failed capture, doubled while failures continue
+const ERROR_BACKOFF_MIN: Duration = Duration::from_millis(10);
+const ERROR_BACKOFF_MAX: Duration = Duration::from_secs(1);
+
 #[derive(Parser, Debug)]
 struct Args {
-    usb: u32,
+    /// usb port index 0-3, ignored when `--cameras` is given
+    usb: Option<u32>,
     // #[arg(short, long, default_value_t = 1920)]
     // width: u32,
 
@@ -18,6 +28,41 @@ struct Args {
 
     // #[arg(short, long, default_value_t = 30)]
     // fps: u32,
+    /// replay a directory of MJPEG frames or a video file instead of the usb camera
+    #[arg(long)]
+    replay: Option<String>,
+
+    /// use generated frames instead of the usb camera
+    #[arg(long)]
+    synthetic: bool,
+
+    /// camera control profile to apply, looked up in `--profiles`
+    #[arg(long)]
+    profile: Option<String>,
+
+    #[arg(long, default_value = "camera_profiles.json")]
+    profiles: String,
+
+    /// how to pick a camera mode when the exact size and fps are not offered
+    #[arg(long, value_enum, default_value_t = NegotiationPolicy::Exact)]
+    negotiate: NegotiationPolicy,
+
+    /// print the controls of the usb camera and exit
+    #[arg(long)]
+    list_controls: bool,
+
+    /// json file mapping camera roles to identities, the `env` entry is used
+    #[arg(long)]
+    cameras: Option<String>,
+
+    /// print every connected camera with its identity and exit
+    #[arg(long)]
+    list_cameras: bool,
+
+    /// aruco finder setting (toml or json) with a `board` section, the board
+    /// pose is published on `camera/board`
+    #[arg(long)]
+    board: Option<String>,
 }
 
 fn main() {
@@ -26,26 +71,96 @@ fn main() {
     let height = 720;
     let fps = 60;
 
-    let (a, b) = match args.usb {
-        0 => (1, 1),
-        1 => (0, 2),
-        2 => (0, 1),
-        3 => (1, 2),
-        _ => {
-            panic!("bad input:{}, only support 0,1,2,3", args.usb)
+    if args.list_cameras {
+        for info in list_cameras() {
+            println!("{info:?}");
+        }
+        return;
+    }
+
+    let mut camera: Box<dyn FrameSource> = if args.synthetic {
+        Box::new(SyntheticSource::new(width, height, fps))
+    } else if let Some(replay) = &args.replay {
+        open_replay(replay, fps).unwrap()
+    } else {
+        let mut camera = match &args.cameras {
+            Some(cameras) => {
+                let cameras = CameraMap::load(cameras).unwrap();
+                let identity = cameras.identity(CameraRole::Env).unwrap();
+                Camera::new_with_identity(identity, width, height, fps, args.negotiate).unwrap()
+            }
+            None => {
+                let (a, b) = match args.usb {
+                    Some(0) => (1, 1),
+                    Some(1) => (0, 2),
+                    Some(2) => (0, 1),
+                    Some(3) => (1, 2),
+                    _ => {
+                        panic!("bad input:{:?}, only support 0,1,2,3", args.usb)
+                    }
+                };
+                let path =
+                    format!("/dev/v4l/by-path/platform-xhci-hcd.{a}-usb-0:{b}:1.0-video-index0");
+                Camera::new_with_path_and_policy(&path, width, height, fps, args.negotiate).unwrap()
+            }
+        };
+        if args.list_controls {
+            for control in camera.controls().unwrap() {
+                println!("{control:?}");
+            }
+            return;
         }
+        if let Some(profile) = &args.profile {
+            let profile = ControlProfile::load(&args.profiles, profile).unwrap();
+            camera.apply_profile(&profile).unwrap();
+        }
+        Box::new(camera)
     };
+    let (width, height) = (camera.width(), camera.height());
 
-    let path = format!("/dev/v4l/by-path/platform-xhci-hcd.{a}-usb-0:{b}:1.0-video-index0");
-    let mut camera = Camera::new_with_path(&path, width, height, fps).unwrap();
+    let board_finder = args.board.as_ref().map(|path| {
+        let setting = ArucoFinderSetting::load(path).unwrap();
+        if setting.board.is_none() {
+            panic!("{path} has no board section");
+        }
+        ArucoFinder::new(setting)
+    });
 
     let session = zenoh::open(config::default()).res().unwrap();
     let compress_pub = session.declare_publisher("camera").res().unwrap();
+    let event_pub = session.declare_publisher("camera/event").res().unwrap();
+    let frame_pub = session.declare_publisher("camera/frame").res().unwrap();
+    let board_pub = session.declare_publisher("camera/board").res().unwrap();
 
     let mut bgr_mat = Mat::default();
     let mut v = Vector::<u8>::new();
+    let mut failures = 0u32;
     loop {
-        let (rgb_raw_data, _time) = camera.capture().unwrap();
+        for event in camera.take_events() {
+            println!("{event:?}");
+            event_pub
+                .put(serde_json::to_value(event).unwrap())
+                .res()
+                .unwrap();
+        }
+        let (rgb_raw_data, time_stamp) = match camera.capture() {
+            Ok(frame) => {
+                failures = 0;
+                frame
+            }
+            Err(Error::EndOfStream) => break,
+            // a disconnected camera keeps retrying inside capture
+            Err(Error::Disconnected) => continue,
+            Err(e) => {
+                failures += 1;
+                let retry_in = ERROR_BACKOFF_MIN
+                    .saturating_mul(1 << (failures - 1).min(16))
+                    .min(ERROR_BACKOFF_MAX);
+                println!("capture failed {failures} times, retry in {retry_in:?}: {e:?}");
+                thread::sleep(retry_in);
+                continue;
+            }
+        };
         let rbg_img = unsafe {
             Mat::new_rows_cols_with_data_unsafe_def(
                 height as i32,
@@ -58,5 +173,23 @@ fn main() {
         cvt_color_def(&rbg_img, &mut bgr_mat, COLOR_RGB2BGR).unwrap();
         imencode_def(".jpg", &bgr_mat, &mut v).unwrap();
         compress_pub.put(v.to_vec()).res().unwrap();
+        let frame_info = FrameInfo {
+            stats: camera.stats(),
+            time_stamp,
+            age: Duration::ZERO,
+            skipped: 0,
+        };
+        frame_pub
+            .put(serde_json::to_value(frame_info).unwrap())
+            .res()
+            .unwrap();
+        if let Some(finder) = &board_finder {
+            if let Some(pose) = finder.find_board(&rbg_img, time_stamp).unwrap() {
+                board_pub
+                    .put(serde_json::to_value(pose).unwrap())
+                    .res()
+                    .unwrap();
+            }
+        }
     }
 }
@@ -0,0 +1,99 @@
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use opencv::core::{Mat, CV_8UC1};
+use rpi::{
+    open_replay, AprilTagFamily, AprilTagFinder, ArucoFinder, ArucoFinderSetting, Error, GrayFrame,
+    MarkerDetector,
+};
+
+/// Run the ArUco and AprilTag detectors over the same recording and print
+/// how often each found a marker and how long it took.
+#[derive(Parser, Debug)]
+struct Args {
+    /// directory of MJPEG frames or a video file
+    replay: String,
+
+    /// marker and camera setting file (TOML or JSON)
+    #[arg(long)]
+    setting: String,
+
+    #[arg(long, value_enum, default_value_t = AprilTagFamily::Tag36h11)]
+    family: AprilTagFamily,
+
+    #[arg(long, default_value_t = 30)]
+    fps: u32,
+
+    /// shrink frames by this factor before marker detection
+    #[arg(long, default_value_t = 1)]
+    detect_scale: u32,
+}
+
+#[derive(Default)]
+struct Summary {
+    frames: u32,
+    detected: u32,
+    markers: usize,
+    score: f64,
+    time: Duration,
+}
+
+fn main() -> Result<(), Error> {
+    let args = Args::parse();
+    let setting = ArucoFinderSetting::load(&args.setting)?;
+    let mut detectors: Vec<(&str, Box<dyn MarkerDetector>)> = vec![
+        ("aruco", Box::new(ArucoFinder::new(setting.clone()))),
+        (
+            "apriltag",
+            Box::new(AprilTagFinder::new(args.family, setting)?),
+        ),
+    ];
+    let mut summaries: Vec<Summary> = detectors.iter().map(|_| Summary::default()).collect();
+
+    let mut source = open_replay(&args.replay, args.fps)?;
+    let mut gray = GrayFrame::new(args.detect_scale);
+    let mut arucos = vec![];
+    loop {
+        let (jpeg, time_stamp) = match source.capture_mjpeg() {
+            Ok(frame) => frame,
+            Err(Error::EndOfStream) => break,
+            Err(_e) => continue,
+        };
+        let gray_data = match gray.decode_jpeg(&jpeg) {
+            Ok(gray_data) => gray_data.as_ptr(),
+            Err(_e) => continue,
+        };
+        let gray_img = unsafe {
+            Mat::new_rows_cols_with_data_unsafe_def(
+                gray.height() as i32,
+                gray.width() as i32,
+                CV_8UC1,
+                gray_data as *mut _,
+            )
+        }?;
+        for ((_, detector), summary) in detectors.iter_mut().zip(summaries.iter_mut()) {
+            let start = Instant::now();
+            detector.find_markers(&gray_img, gray.scale(), time_stamp, &mut arucos)?;
+            summary.time += start.elapsed();
+            summary.frames += 1;
+            if !arucos.is_empty() {
+                summary.detected += 1;
+            }
+            summary.markers += arucos.len();
+            summary.score += arucos.iter().map(|a| a.quality.score).sum::<f64>();
+        }
+    }
+
+    for ((name, _), s) in detectors.iter().zip(summaries.iter()) {
+        let frames = s.frames.max(1);
+        println!(
+            "{name}: detected in {}/{} frames, {:.2} markers per frame, mean score {:.3}, {:.2} ms per frame",
+            s.detected,
+            s.frames,
+            s.markers as f64 / frames as f64,
+            s.score / s.markers.max(1) as f64,
+            s.time.as_secs_f64() * 1000. / frames as f64,
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use std::path::Path;
+
+use clap::Parser;
+use rpi::{Error, SoftFinger};
+
+/// Convert a PyTorch `.pth` finger model to burn's `.mpk` format and check
+/// that both give the same forces.
+#[derive(Parser, Debug)]
+struct Args {
+    /// PyTorch checkpoint, its `<model>.json` metadata is used when present
+    input: String,
+
+    /// defaults to the input with a `.mpk` extension
+    #[arg(long)]
+    output: Option<String>,
+
+    /// inputs compared between the two models
+    #[arg(long, default_value_t = 100)]
+    samples: usize,
+
+    /// largest difference allowed, relative to the force magnitude
+    #[arg(long, default_value_t = 1e-5)]
+    tolerance: f32,
+}
+
+// inputs spread around the training mean, reproducible without a rng crate
+fn samples(finger: &SoftFinger, count: usize) -> Vec<Vec<f32>> {
+    let metadata = finger.metadata();
+    let mut state = 0x2545_f491_u32;
+    (0..count)
+        .map(|_| {
+            let mut features = vec![0.; metadata.input_mean.len()];
+            for (i, x) in features.iter_mut().enumerate() {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let u = (state >> 8) as f32 / (1 << 24) as f32 * 4. - 2.;
+                *x = metadata.input_mean[i] + metadata.input_std[i] * u;
+            }
+            features
+        })
+        .collect()
+}
+
+fn main() -> Result<(), Error> {
+    let args = Args::parse();
+    let output = args.output.clone().unwrap_or_else(|| {
+        Path::new(&args.input)
+            .with_extension("mpk")
+            .to_string_lossy()
+            .to_string()
+    });
+
+    let pytorch = SoftFinger::load_pt(&args.input)?;
+    pytorch.save_mpk(&output)?;
+    let native = SoftFinger::new_mpk(&output)?;
+    println!("written to {output}");
+
+    let mut worst = 0f32;
+    for features in samples(&pytorch, args.samples) {
+        let a = pytorch.predict_features(&features)?.value;
+        let b = native.predict_features(&features)?.value;
+        worst = worst.max((a - b).norm() / a.norm().max(1.));
+    }
+    println!(
+        "largest relative difference {worst:e} over {} samples",
+        args.samples
+    );
+    if worst > args.tolerance {
+        return Err(Error::Config(format!(
+            "converted model differs by {worst:e}, more than {:e}",
+            args.tolerance
+        )));
+    }
+    Ok(())
+}
@@ -1,119 +1,544 @@
-use clap::Parser;
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use clap::{ArgGroup, Parser};
 use opencv::{
-    core::{Mat, Vector, VectorToVec, CV_8UC3},
-    imgcodecs::imencode_def,
-    imgproc::{cvt_color_def, COLOR_RGB2BGR},
+    core::{Mat, Point2f, Vector, VectorToVec, CV_8UC1},
+    imgcodecs::{imdecode, imencode_def, IMREAD_COLOR},
 };
 use rpi::{
-    ArucoFinder, ArucoFinderSetting, ArucoIntrinsic, Camera, CameraDistortion,
-    CameraIntrinsic, FingerForceData, SoftFinger,
+    open_replay, Aruco, ArucoFinder, ArucoFinderSetting, ArucoIntrinsic, Camera, CameraCalibration,
+    CameraMap, CameraRole, CaptureThread, ControlProfile, DetectionInfo, Error, FingerContext,
+    FingerForceData, Force, FrameInfo, FrameSource, MarkerDetector, MarkerTracker,
+    NegotiationPolicy, RestPose, RestPoseCapture, RoiDetector, RoiSetting, SoftFinger,
+    SyntheticSource, TrackedAruco, TrackerSetting,
 };
-use zenoh::prelude::sync::*;
+#[cfg(feature = "apriltag")]
+use rpi::{AprilTagFamily, AprilTagFinder};
+use zenoh::{prelude::sync::*, publication::Publisher, subscriber::FlumeSubscriber, Session};
 
 #[derive(Parser, Debug)]
+#[command(group(
+    ArgGroup::new("camera_setting")
+        .required(true)
+        .multiple(true)
+        .args(["setting", "calibration"]),
+))]
 struct Args {
     // #[arg(default_value_t = left)]
+    /// left, right, or both to run the two fingers with one shared model
     direct: String,
     // #[arg(short, long)]
+    /// finger model, `.mpk` from `convert-model`, or a PyTorch `.pth` when
+    /// built with the `pytorch` feature
     path: String,
-    // #[arg(short, long)]
-    usb: u32,
-
     fps: u32,
+
+    /// usb port index 0-3, ignored when `--cameras` is given
+    #[arg(long)]
+    usb: Option<u32>,
+
+    /// replay a directory of MJPEG frames or a video file instead of the usb camera
+    #[arg(long)]
+    replay: Option<String>,
+
+    /// use generated frames instead of the usb camera
+    #[arg(long)]
+    synthetic: bool,
+
+    /// camera control profile to apply, looked up in `--profiles`
+    #[arg(long)]
+    profile: Option<String>,
+
+    #[arg(long, default_value = "camera_profiles.json")]
+    profiles: String,
+
+    /// how to pick a camera mode when the exact size and fps are not offered
+    #[arg(long, value_enum, default_value_t = NegotiationPolicy::Exact)]
+    negotiate: NegotiationPolicy,
+
+    /// json file mapping camera roles to identities, required for `both`
+    #[arg(long)]
+    cameras: Option<String>,
+
+    /// marker and camera setting file (TOML or JSON), overrides `--calibration`
+    #[arg(long)]
+    setting: Option<String>,
+
+    /// camera calibration written by `calibrate`
+    #[arg(long)]
+    calibration: Option<String>,
+
+    /// frames kept for processing, 1 drops everything but the newest
+    #[arg(long, default_value_t = 1)]
+    queue: usize,
+
+    /// skip force prediction when the marker quality score is below this
+    #[arg(long, default_value_t = 0.0)]
+    min_quality: f64,
+
+    /// filter markers across frames and bridge short detection gaps
+    #[arg(long)]
+    track: bool,
+
+    /// how long the tracker keeps predicting a lost marker
+    #[arg(long, default_value_t = 100)]
+    max_prediction_ms: u64,
+
+    /// shrink frames by this factor before marker detection
+    #[arg(long, default_value_t = 1)]
+    detect_scale: u32,
+
+    /// marker IDs of this finger, most preferred first, overrides the setting
+    #[arg(long, value_delimiter = ',')]
+    marker_ids: Vec<i32>,
+
+    /// rest pose baseline, written on tare, defaults to `rest_pose_<direct>.json`
+    #[arg(long)]
+    rest_pose: Option<String>,
+
+    /// frames averaged on tare unless the command names a count
+    #[arg(long, default_value_t = 30)]
+    tare_frames: usize,
+
+    /// also publish frames with markers, rejected candidates and axes drawn
+    /// on `finger/<direct>/image/annotated`
+    #[arg(long)]
+    annotate: bool,
+
+    /// detect AprilTags of this family instead of ArUco markers
+    #[cfg(feature = "apriltag")]
+    #[arg(long, value_enum)]
+    april_tag: Option<AprilTagFamily>,
+
+    /// search only around the last markers, falling back to the full frame
+    /// on a miss
+    #[arg(long)]
+    roi: bool,
+
+    /// with `--roi`, still search the full frame every this many frames
+    #[arg(long, default_value_t = 30)]
+    roi_full_every: u32,
 }
-fn main() {
-    let args = Args::parse();
+
+fn open_camera(args: &Args, is_right: bool) -> Box<dyn FrameSource + Send> {
     let width: u32 = 640;
     let height = 480;
     let fps = args.fps;
-    let (a, b) = match args.usb {
-        0 => (1, 1),
-        1 => (0, 2),
-        2 => (0, 1),
-        3 => (1, 2),
-        _ => {
-            panic!("bad input:{}, only support 0,1,2,3", args.usb)
+    if args.synthetic {
+        return Box::new(SyntheticSource::new(width, height, fps));
+    }
+    if let Some(replay) = &args.replay {
+        return open_replay(replay, fps).unwrap();
+    }
+    let mut camera = match &args.cameras {
+        Some(cameras) => {
+            let role = if is_right {
+                CameraRole::RightFinger
+            } else {
+                CameraRole::LeftFinger
+            };
+            let cameras = CameraMap::load(cameras).unwrap();
+            let identity = cameras.identity(role).unwrap();
+            Camera::new_with_identity(identity, width, height, fps, args.negotiate).unwrap()
+        }
+        None => {
+            let (a, b) = match args.usb {
+                Some(0) => (1, 1),
+                Some(1) => (0, 2),
+                Some(2) => (0, 1),
+                Some(3) => (1, 2),
+                _ => {
+                    panic!("bad input:{:?}, only support 0,1,2,3", args.usb)
+                }
+            };
+            let path = format!("/dev/v4l/by-path/platform-xhci-hcd.{a}-usb-0:{b}:1.0-video-index0");
+            Camera::new_with_path_and_policy(&path, width, height, fps, args.negotiate).unwrap()
         }
     };
-    let path = format!("/dev/v4l/by-path/platform-xhci-hcd.{a}-usb-0:{b}:1.0-video-index0");
-    let mut camera = Camera::new_with_path(&path, width, height, fps).unwrap();
-
-    let mut bgr_mat = Mat::default();
+    if let Some(profile) = &args.profile {
+        let profile = ControlProfile::load(&args.profiles, profile).unwrap();
+        camera.apply_profile(&profile).unwrap();
+    }
+    Box::new(camera)
+}
 
-    let soft_finger = SoftFinger::new_pt(&args.path);
-    let mut arucos = vec![];
-    let session = zenoh::open(config::default()).res().unwrap();
-    let is_right = match args.direct.as_str() {
-        "right" => true,
-        "left" => false,
-        _ => {
-            panic!("left or right")
+fn finder_setting(args: &Args, width: u32, height: u32) -> ArucoFinderSetting {
+    let aruco_intrinsic = ArucoIntrinsic::new_with_marker_length(0.05);
+    let mut setting = match (&args.setting, &args.calibration) {
+        (Some(setting), _) => ArucoFinderSetting::load(setting).unwrap(),
+        (None, Some(calibration)) => {
+            let calibration = CameraCalibration::load(calibration).unwrap();
+            ArucoFinderSetting::from_calibration(aruco_intrinsic, &calibration, width, height)
+                .unwrap()
         }
+        // clap asks for one of the two
+        (None, None) => unreachable!(),
     };
-    let base_key = if is_right {
-        "finger/right"
-    } else {
-        "finger/left"
-    };
-    let force_pub = session
-        .declare_publisher(format!("{base_key}/force"))
-        .res()
-        .unwrap();
-    let image_pub = session
-        .declare_publisher(format!("{base_key}/image"))
-        .res()
-        .unwrap();
-    // let cmd_subscriber = session.declare_subscriber("cmd/record").res().unwrap();
-
-    let cx = 655.3664;
-    let cy = 367.5246;
-    let fx = 971.2252;
-    let fy = 970.7470;
-    let k1 = 0.0097;
-    let k2 = -0.00745;
-    let k3 = 0.00;
-    let p1 = 0.00;
-    let p2 = 0.00;
-    let setting = ArucoFinderSetting {
-        aruco_intrinsic: ArucoIntrinsic::new_with_marker_length(0.05),
-        camera_intrinsic: CameraIntrinsic { cx, cy, fx, fy },
-        camera_distortion: CameraDistortion::from_5_params(k1, k2, p1, p2, k3),
-    };
-    let aruco_finder = ArucoFinder::new(setting);
-    // let mut csv_file = CSVFile::<FingerForceData>::new();
-    let mut v = Vector::<u8>::new();
-    loop {
-        let (rgb_raw_data, time_stamp) = match camera.capture() {
-            Ok((rgb_raw_data, time_stamp)) => (rgb_raw_data, time_stamp),
+    if !args.marker_ids.is_empty() {
+        setting.marker_ids = args.marker_ids.clone();
+    }
+    setting.validate_for_image(width, height).unwrap();
+    setting
+}
+
+/// A frame went through detection, `marker` is what the model should see.
+struct Step {
+    time_stamp: Duration,
+    marker: Option<(Aruco, bool)>,
+}
+
+/// Camera, detection and publishers of one finger.
+struct Finger<'a> {
+    camera: CaptureThread,
+    aruco_finder: ArucoFinder,
+    // used instead of `aruco_finder` for detection when set
+    april_tag: Option<Box<dyn MarkerDetector>>,
+    roi: Option<RoiDetector>,
+    tracker: Option<MarkerTracker>,
+    tracked: Vec<TrackedAruco>,
+    arucos: Vec<Aruco>,
+    rejected: Vector<Vector<Point2f>>,
+    annotated: Vector<u8>,
+    context: FingerContext,
+    rest_pose_path: String,
+    tare: Option<RestPoseCapture>,
+    prediction_error: Option<String>,
+    force_pub: Publisher<'a>,
+    image_pub: Publisher<'a>,
+    annotated_pub: Publisher<'a>,
+    event_pub: Publisher<'a>,
+    frame_pub: Publisher<'a>,
+    detection_pub: Publisher<'a>,
+    aruco_pub: Publisher<'a>,
+    displacement_pub: Publisher<'a>,
+    rest_pose_pub: Publisher<'a>,
+    tare_sub: FlumeSubscriber<'a>,
+}
+
+impl<'a> Finger<'a> {
+    fn new(args: &Args, is_right: bool, session: &'a Session, soft_finger: &SoftFinger) -> Self {
+        let direct = if is_right { "right" } else { "left" };
+        // capture runs ahead on its own thread, the loop always gets the
+        // newest frame. Detection only needs luminance, taken from the raw
+        // frame where there is one, the preview goes out as JPEG
+        let camera = CaptureThread::spawn_with_gray(
+            open_camera(args, is_right),
+            args.queue,
+            args.detect_scale,
+        );
+        soft_finger
+            .check_resolution(camera.width(), camera.height())
+            .unwrap();
+
+        let base_key = format!("finger/{direct}");
+        let publisher = |key: &str| {
+            session
+                .declare_publisher(format!("{base_key}/{key}"))
+                .res()
+                .unwrap()
+        };
+        // let cmd_subscriber = session.declare_subscriber("cmd/record").res().unwrap();
+
+        let setting = finder_setting(args, camera.width(), camera.height());
+        #[cfg(feature = "apriltag")]
+        let april_tag = args.april_tag.map(|family| {
+            Box::new(AprilTagFinder::new(family, setting.clone()).unwrap())
+                as Box<dyn MarkerDetector>
+        });
+        #[cfg(not(feature = "apriltag"))]
+        let april_tag = None;
+        let mut context = FingerContext::default();
+        context
+            .set_camera(&setting.camera_intrinsic, &setting.camera_distortion)
+            .unwrap();
+        let rest_pose_path = match &args.rest_pose {
+            // one finger keeps the given name, two get it with the side added
+            Some(path) if args.direct != "both" => path.clone(),
+            Some(path) => Path::new(path)
+                .with_extension(format!("{direct}.json"))
+                .to_string_lossy()
+                .to_string(),
+            None => format!("rest_pose_{direct}.json"),
+        };
+        context.set_rest_pose(
+            Path::new(&rest_pose_path)
+                .exists()
+                .then(|| RestPose::load(&rest_pose_path).unwrap()),
+        );
+
+        Self {
+            camera,
+            aruco_finder: ArucoFinder::new(setting),
+            april_tag,
+            roi: args.roi.then(|| {
+                RoiDetector::new(RoiSetting {
+                    full_frame_every: args.roi_full_every,
+                    ..Default::default()
+                })
+            }),
+            tracker: args.track.then(|| {
+                MarkerTracker::new(TrackerSetting {
+                    max_prediction: Duration::from_millis(args.max_prediction_ms),
+                    ..Default::default()
+                })
+            }),
+            tracked: vec![],
+            arucos: vec![],
+            rejected: Vector::new(),
+            annotated: Vector::new(),
+            context,
+            rest_pose_path,
+            tare: None,
+            prediction_error: None,
+            force_pub: publisher("force"),
+            image_pub: publisher("image"),
+            annotated_pub: publisher("image/annotated"),
+            event_pub: publisher("camera/event"),
+            frame_pub: publisher("frame"),
+            detection_pub: publisher("detection"),
+            aruco_pub: publisher("aruco"),
+            displacement_pub: publisher("displacement"),
+            rest_pose_pub: publisher("rest_pose"),
+            tare_sub: session
+                .declare_subscriber(format!("{base_key}/tare"))
+                .res()
+                .unwrap(),
+        }
+    }
+
+    /// Take the next frame and find the marker in it, `None` when no frame
+    /// could be used this time.
+    fn step(&mut self, args: &Args) -> Result<Option<Step>, Error> {
+        if let Ok(cmd) = self.tare_sub.try_recv() {
+            // the payload may name how many frames to average
+            let frames = serde_json::Value::try_from(cmd.value)
+                .ok()
+                .and_then(|v| v.as_u64())
+                .map_or(args.tare_frames, |n| n as usize);
+            println!("tare start, {frames} frames");
+            self.tare = Some(RestPoseCapture::new(frames));
+        }
+        for event in self.camera.take_events().unwrap() {
+            println!("{event:?}");
+            self.event_pub
+                .put(serde_json::to_value(event).unwrap())
+                .res()
+                .unwrap();
+        }
+        let frame = match self.camera.recv() {
+            Ok(frame) => frame,
+            Err(Error::EndOfStream) => return Err(Error::EndOfStream),
             Err(_e) => {
                 // println!("{e:?}");
-                continue;
+                return Ok(None);
             }
         };
+        let time_stamp = frame.time_stamp;
+        let jpeg = frame.jpeg;
 
-        let rbg_img = unsafe {
+        let gray = frame.gray.unwrap();
+        let gray_data = gray.data().as_ptr();
+        let gray_img = unsafe {
             Mat::new_rows_cols_with_data_unsafe_def(
-                height as i32,
-                width as i32,
-                CV_8UC3,
-                rgb_raw_data.as_ptr() as *mut _,
+                gray.height() as i32,
+                gray.width() as i32,
+                CV_8UC1,
+                gray_data as *mut _,
             )
         }
         .unwrap();
-        cvt_color_def(&rbg_img, &mut bgr_mat, COLOR_RGB2BGR).unwrap();
-        imencode_def(".jpg", &bgr_mat, &mut v).unwrap();
-        image_pub.put(v.to_vec()).res().unwrap();
-        aruco_finder
-            .find(&rbg_img, time_stamp, &mut arucos)
+        let arucos = &mut self.arucos;
+        let detection = if let Some(roi) = self.roi.as_mut() {
+            let detector: &mut dyn MarkerDetector = match self.april_tag.as_mut() {
+                Some(finder) => finder.as_mut(),
+                None => &mut self.aruco_finder,
+            };
+            roi.find(
+                detector,
+                &gray_img,
+                gray.scale(),
+                time_stamp,
+                arucos,
+                args.annotate.then_some(&mut self.rejected),
+            )
+            .unwrap()
+        } else {
+            let start = Instant::now();
+            match self.april_tag.as_mut() {
+                Some(finder) => finder
+                    .find_markers(&gray_img, gray.scale(), time_stamp, arucos)
+                    .unwrap(),
+                None => self
+                    .aruco_finder
+                    .find_with_rejected(
+                        &gray_img,
+                        gray.scale(),
+                        time_stamp,
+                        arucos,
+                        args.annotate.then_some(&mut self.rejected),
+                    )
+                    .unwrap(),
+            }
+            DetectionInfo {
+                time_stamp,
+                latency: start.elapsed(),
+                roi: None,
+                markers: arucos.len(),
+            }
+        };
+        self.detection_pub
+            .put(serde_json::to_value(detection).unwrap())
+            .res()
             .unwrap();
-        let force_data = FingerForceData {
-            force: arucos.first().map(|aruco| soft_finger.predict_force(aruco)),
+        if args.annotate {
+            let mut canvas = imdecode(&Vector::<u8>::from_slice(&jpeg), IMREAD_COLOR).unwrap();
+            self.aruco_finder
+                .annotate(&mut canvas, arucos, &self.rejected)
+                .unwrap();
+            imencode_def(".jpg", &canvas, &mut self.annotated).unwrap();
+            self.annotated_pub
+                .put(self.annotated.to_vec())
+                .res()
+                .unwrap();
+        }
+        self.image_pub.put(jpeg).res().unwrap();
+        let frame_info = FrameInfo {
+            stats: frame.stats,
             time_stamp,
+            age: frame.age(),
+            skipped: self.camera.overwritten().unwrap(),
+        };
+        self.frame_pub
+            .put(serde_json::to_value(frame_info).unwrap())
+            .res()
+            .unwrap();
+        self.aruco_pub
+            .put(serde_json::to_value(&*arucos).unwrap())
+            .res()
+            .unwrap();
+        let marker = match self.tracker.as_mut() {
+            Some(tracker) => {
+                tracker.update(arucos, time_stamp, &mut self.tracked);
+                let setting = self.aruco_finder.setting();
+                self.tracked
+                    .iter()
+                    .min_by_key(|t| setting.marker_rank(t.aruco.id))
+                    .map(|t| (t.aruco, t.predicted))
+            }
+            None => self
+                .aruco_finder
+                .select(arucos)
+                .map(|aruco| (*aruco, false)),
+        };
+        let marker = marker.filter(|(aruco, _)| aruco.quality.score >= args.min_quality);
+        if let (Some(capture), Some((aruco, false))) = (self.tare.as_mut(), marker) {
+            if let Some(pose) = capture.add(&aruco) {
+                println!("tare end, {pose:?}");
+                pose.save(&self.rest_pose_path).unwrap();
+                self.rest_pose_pub
+                    .put(serde_json::to_value(&pose).unwrap())
+                    .res()
+                    .unwrap();
+                self.context.set_rest_pose(Some(pose));
+                self.tare = None;
+            }
+        }
+        if let Some(displacement) = self
+            .context
+            .rest_pose()
+            .zip(marker)
+            .and_then(|(rest, (aruco, _))| rest.displacement(&aruco))
+        {
+            self.displacement_pub
+                .put(serde_json::to_value(displacement).unwrap())
+                .res()
+                .unwrap();
+        }
+        Ok(Some(Step { time_stamp, marker }))
+    }
+
+    // printed once, not for every frame, until predictions work again
+    fn prediction_failed(&mut self, error: Error) {
+        let reason = format!("{error:?}");
+        if self.prediction_error.as_ref() != Some(&reason) {
+            println!("prediction failed: {reason}");
+            self.prediction_error = Some(reason);
+        }
+    }
+
+    fn publish_force(&self, step: &Step, force: Option<Force>) {
+        let force_data = FingerForceData {
+            force,
+            time_stamp: step.time_stamp,
+            predicted: step.marker.is_some_and(|(_, predicted)| predicted),
         };
         // println!("{force_data:?}");
-        force_pub
+        self.force_pub
             .put(serde_json::to_value(force_data).unwrap())
             .res()
             .unwrap();
     }
 }
+
+fn main() {
+    let args = Args::parse();
+    let sides = match args.direct.as_str() {
+        "right" => vec![true],
+        "left" => vec![false],
+        "both" => vec![false, true],
+        _ => {
+            panic!("left, right or both")
+        }
+    };
+    if sides.len() > 1 && args.cameras.is_none() && args.replay.is_none() && !args.synthetic {
+        panic!("both fingers need --cameras to tell the two cameras apart");
+    }
+    if args.usb.is_none() && args.cameras.is_none() && args.replay.is_none() && !args.synthetic {
+        panic!("give the camera with --usb or --cameras");
+    }
+
+    // one model for every finger, their detections go through it as a batch
+    let soft_finger = SoftFinger::load(&args.path).unwrap();
+    let session = zenoh::open(config::default()).res().unwrap();
+    let mut fingers: Vec<Finger> = sides
+        .into_iter()
+        .map(|is_right| Finger::new(&args, is_right, &session, &soft_finger))
+        .collect();
+    // let mut csv_file = CSVFile::<FingerForceData>::new();
+    loop {
+        let mut steps = vec![];
+        for (index, finger) in fingers.iter_mut().enumerate() {
+            match finger.step(&args) {
+                Ok(Some(step)) => steps.push((index, step)),
+                Ok(None) => {}
+                Err(_e) => return,
+            }
+        }
+        let items: Vec<(&Aruco, &FingerContext)> = steps
+            .iter()
+            .filter_map(|(index, step)| {
+                let (aruco, _) = step.marker.as_ref()?;
+                Some((aruco, &fingers[*index].context))
+            })
+            .collect();
+        let mut forces = soft_finger.predict_batch(&items).into_iter();
+        for (index, step) in &steps {
+            let finger = &mut fingers[*index];
+            let force = match step.marker.map(|_| forces.next().unwrap()) {
+                Some(Ok(force)) => {
+                    finger.prediction_error = None;
+                    Some(force)
+                }
+                Some(Err(e)) => {
+                    finger.prediction_failed(e);
+                    None
+                }
+                None => None,
+            };
+            finger.publish_force(step, force);
+        }
+    }
+}
@@ -62,6 +62,7 @@ fn mock_finger_data(key: &str) {
                 value: Vector6::new(x, x, x, x, x, x),
             }),
             time_stamp: start.elapsed(),
+            predicted: false,
         };
         force_pub
             .put(serde_json::to_value(force_data).unwrap())
@@ -52,6 +52,32 @@ impl ValueToMsg for FingerCamera<Right> {
     }
 }
 
+struct AnnotatedFingerCamera<L> {
+    _p: std::marker::PhantomData<L>,
+}
+
+impl ValueToMsg for AnnotatedFingerCamera<Left> {
+    fn key() -> String {
+        "finger/left/image/annotated".into()
+    }
+
+    fn value_to_msg(s: Sample) -> Message {
+        let data: Vec<u8> = s.value.try_into().unwrap();
+        Message::Binary(data)
+    }
+}
+
+impl ValueToMsg for AnnotatedFingerCamera<Right> {
+    fn key() -> String {
+        "finger/right/image/annotated".into()
+    }
+
+    fn value_to_msg(s: Sample) -> Message {
+        let data: Vec<u8> = s.value.try_into().unwrap();
+        Message::Binary(data)
+    }
+}
+
 struct FingerForce<L> {
     _p: std::marker::PhantomData<L>,
 }
@@ -91,6 +117,14 @@ async fn server<A: ToSocketAddrs>(addr: A) {
             "/right_finger/image",
             get(streaming_handler::<FingerCamera<Right>>),
         )
+        .route(
+            "/left_finger/image/annotated",
+            get(streaming_handler::<AnnotatedFingerCamera<Left>>),
+        )
+        .route(
+            "/right_finger/image/annotated",
+            get(streaming_handler::<AnnotatedFingerCamera<Right>>),
+        )
         .route(
             "/left_finger/force",
             get(streaming_handler::<FingerForce<Left>>),